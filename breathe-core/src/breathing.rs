@@ -0,0 +1,661 @@
+use crate::config::{Config, SettingName};
+use crate::constants;
+use crate::curve;
+use crate::indicator::BreathIndicator;
+use crate::preset::Preset;
+
+// Non-blocking breathing cycle, advanced by polling with the current time
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Phase {
+    Inhale,
+    TopUp,
+    Hold,
+    Exhale,
+    Airless,
+}
+
+const PHASE_COUNT: usize = 5;
+
+// In the order they're breathed
+const PHASES: [Phase; PHASE_COUNT] = [
+    Phase::Inhale,
+    Phase::TopUp,
+    Phase::Hold,
+    Phase::Exhale,
+    Phase::Airless,
+];
+
+// With a top-up, the first inhale only fills this far, leaving room for the second
+const TOP_UP_FROM_PCT: u8 = 80;
+
+impl Phase {
+    pub fn as_str<'a>(&self) -> &'a str {
+        use Phase::*;
+        match self {
+            Inhale => return "Inhale",
+            TopUp => return "Top Up",
+            Hold => return "Hold",
+            Exhale => return "Exhale",
+            Airless => return "Airless",
+        }
+    }
+
+    pub fn next(&self) -> Phase {
+        use Phase::*;
+        match self {
+            Inhale => return TopUp,
+            TopUp => return Hold,
+            Hold => return Exhale,
+            Exhale => return Airless,
+            Airless => return Inhale,
+        }
+    }
+}
+
+// Where the breath is at a moment, for outputs to show
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Breath {
+    pub phase: Phase,
+    // How full the breath is, from 0 to `curve::ONE`
+    pub level: u32,
+    pub paused: bool,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Timings {
+    pub inhale_ms: u16,
+    pub top_up_ms: u16,
+    pub hold_ms: u16,
+    pub exhale_ms: u16,
+    pub airless_ms: u16,
+}
+
+impl Timings {
+    pub fn from_config(config: &Config) -> Self {
+        use SettingName::*;
+        Timings {
+            inhale_ms: config
+                .get(InhaleTimeMs)
+                .unwrap_or_else(|| return constants::MIN_INHALE_TIME_MS),
+            top_up_ms: config
+                .get(TopUpTimeMs)
+                .unwrap_or_else(|| return constants::MIN_TOP_UP_TIME_MS),
+            hold_ms: config
+                .get(HoldTimeMs)
+                .unwrap_or_else(|| return constants::DEFAULT_HOLD_TIME_MS),
+            exhale_ms: config
+                .get(ExhaleTimeMs)
+                .unwrap_or_else(|| return constants::MIN_EXHALE_TIME_MS),
+            airless_ms: config
+                .get(AirlessTimeMs)
+                .unwrap_or_else(|| return constants::DEFAULT_AIRLESS_TIME_MS),
+        }
+    }
+
+    // A preset's pattern, whatever the config says
+    pub fn from_preset(preset: Preset) -> Self {
+        use SettingName::*;
+        let mut timings = Timings {
+            inhale_ms: 0,
+            top_up_ms: 0,
+            hold_ms: 0,
+            exhale_ms: 0,
+            airless_ms: 0,
+        };
+        for (setting, value) in preset.durations() {
+            match setting {
+                InhaleTimeMs => timings.inhale_ms = value,
+                TopUpTimeMs => timings.top_up_ms = value,
+                HoldTimeMs => timings.hold_ms = value,
+                ExhaleTimeMs => timings.exhale_ms = value,
+                AirlessTimeMs => timings.airless_ms = value,
+                _ => {}
+            }
+        }
+        return timings;
+    }
+
+    pub fn duration_ms(&self, phase: Phase) -> u16 {
+        use Phase::*;
+        match phase {
+            Inhale => return self.inhale_ms,
+            TopUp => return self.top_up_ms,
+            Hold => return self.hold_ms,
+            Exhale => return self.exhale_ms,
+            Airless => return self.airless_ms,
+        }
+    }
+
+    pub fn cycle_ms(&self) -> u32 {
+        PHASES
+            .iter()
+            .map(|phase| self.duration_ms(*phase) as u32)
+            .sum()
+    }
+
+    // The phase and level, from 0 to `curve::ONE`, at a point in the cycle
+    // measured from the start of the inhale
+    pub fn breath_at(&self, position_ms: u32) -> (Phase, u32) {
+        let mut start = 0;
+        for phase in PHASES {
+            let duration = self.duration_ms(phase) as u32;
+            if position_ms < start + duration {
+                return (phase, self.level_in(phase, position_ms - start));
+            }
+            start += duration;
+        }
+        return (Phase::Airless, 0);
+    }
+
+    // Level some way into a phase
+    fn level_in(&self, phase: Phase, elapsed_ms: u32) -> u32 {
+        let (from, to) = self.levels(phase);
+        let from = from as i64 * curve::ONE as i64 / 100;
+        let to = to as i64 * curve::ONE as i64 / 100;
+        let duration = self.duration_ms(phase) as i64;
+        if duration == 0 {
+            return to as u32;
+        }
+        let elapsed = (elapsed_ms as i64).min(duration);
+        return (from + (to - from) * elapsed / duration) as u32;
+    }
+
+    // Levels a phase goes from and to, 0 (empty) to 100 (full)
+    fn levels(&self, phase: Phase) -> (u8, u8) {
+        use Phase::*;
+        let inhale_peak = match self.top_up_ms {
+            0 => 100,
+            _ => TOP_UP_FROM_PCT,
+        };
+        match phase {
+            Inhale => return (0, inhale_peak),
+            TopUp => return (inhale_peak, 100),
+            Hold => return (100, 100),
+            Exhale => return (100, 0),
+            Airless => return (0, 0),
+        }
+    }
+}
+
+pub struct BreathingEngine {
+    timings: Timings,
+    phase: Phase,
+    phase_start_ms: u64,
+    // When the first poll was, moved on by pauses like `phase_start_ms`
+    start_ms: u64,
+    started: bool,
+    paused_at_ms: Option<u64>,
+}
+
+impl BreathingEngine {
+    pub fn new(timings: Timings) -> Self {
+        BreathingEngine {
+            timings,
+            phase: Phase::Inhale,
+            phase_start_ms: 0,
+            start_ms: 0,
+            started: false,
+            paused_at_ms: None,
+        }
+    }
+
+    // New timings apply to the phase in progress, measured from its start.
+    // Returns true if anything changed, so outputs can be re-timed.
+    pub fn set_timings(&mut self, timings: Timings) -> bool {
+        if self.timings == timings {
+            return false;
+        }
+        self.timings = timings;
+        return true;
+    }
+
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    // Freeze the cycle where it is, until resumed
+    pub fn pause(&mut self, now_ms: u64) {
+        if self.paused_at_ms.is_none() {
+            self.paused_at_ms = Some(now_ms);
+        }
+    }
+
+    // Carry on from where the cycle was paused
+    pub fn resume(&mut self, now_ms: u64) {
+        if let Some(paused_at) = self.paused_at_ms.take() {
+            let paused_for = now_ms.saturating_sub(paused_at);
+            self.phase_start_ms += paused_for;
+            self.start_ms += paused_for;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at_ms.is_some()
+    }
+
+    // Advance the cycle to `now_ms`, returning the phase just entered, if any.
+    // Zero-length phases are skipped over.
+    pub fn poll(&mut self, now_ms: u64) -> Option<Phase> {
+        if self.is_paused() {
+            return None;
+        }
+        if !self.started {
+            self.started = true;
+            self.phase = Phase::Inhale;
+            self.phase_start_ms = now_ms;
+            self.start_ms = now_ms;
+            self.skip_empty_phases();
+            return Some(self.phase);
+        }
+        // Nothing to breathe through, so stay put rather than entering a phase
+        // on every poll
+        if self.timings.cycle_ms() == 0 {
+            return None;
+        }
+
+        let mut entered = None;
+        for _ in 0..PHASE_COUNT {
+            let end = self.phase_end_ms();
+            if now_ms < end {
+                break;
+            }
+            self.phase = self.phase.next();
+            self.phase_start_ms = end;
+            entered = Some(self.phase);
+        }
+
+        // More than a whole cycle behind, so restart the current phase from now
+        if now_ms >= self.phase_end_ms() {
+            self.phase_start_ms = now_ms;
+        }
+
+        if entered.is_some() {
+            self.skip_empty_phases();
+            return Some(self.phase);
+        }
+        return None;
+    }
+
+    // How far through the current phase we are, from 0 to `curve::ONE`
+    pub fn progress(&self, now_ms: u64) -> u32 {
+        let now_ms = self.frozen(now_ms);
+        let duration = self.timings.duration_ms(self.phase) as u64;
+        if duration == 0 {
+            return curve::ONE;
+        }
+        let elapsed = now_ms.saturating_sub(self.phase_start_ms).min(duration);
+        return (elapsed * curve::ONE as u64 / duration) as u32;
+    }
+
+    // How full the breath is right now, from 0 (empty) to `curve::ONE` (full),
+    // for outputs that step themselves along the breath
+    pub fn level(&self, now_ms: u64) -> u32 {
+        let elapsed = self.frozen(now_ms).saturating_sub(self.phase_start_ms);
+        return self
+            .timings
+            .level_in(self.phase, elapsed.min(u32::MAX as u64) as u32);
+    }
+
+    // Everything an output needs to show the breath right now
+    pub fn breath(&self, now_ms: u64) -> Breath {
+        return Breath {
+            phase: self.phase,
+            level: self.level(now_ms),
+            paused: self.is_paused(),
+        };
+    }
+
+    // Poll, then bring every indicator up to date. Returns the phase just
+    // entered, if any, like `poll`.
+    pub fn drive(
+        &mut self,
+        now_ms: u64,
+        indicators: &mut [&mut dyn BreathIndicator],
+    ) -> Option<Phase> {
+        let entered = self.poll(now_ms);
+        for indicator in indicators.iter_mut() {
+            if let Some(phase) = entered {
+                indicator.on_phase(phase, now_ms);
+            }
+            indicator.render(self, now_ms);
+        }
+        return entered;
+    }
+
+    // How far into the cycle we are, measured from the start of the inhale
+    pub fn cycle_position_ms(&self, now_ms: u64) -> u32 {
+        let before: u32 = PHASES
+            .iter()
+            .take_while(|phase| **phase != self.phase)
+            .map(|phase| self.timings.duration_ms(*phase) as u32)
+            .sum();
+        let duration = self.timings.duration_ms(self.phase) as u64;
+        let elapsed = self
+            .frozen(now_ms)
+            .saturating_sub(self.phase_start_ms)
+            .min(duration);
+        return before + elapsed as u32;
+    }
+
+    // Time spent breathing since the first poll, not counting pauses
+    pub fn breathing_ms(&self, now_ms: u64) -> u64 {
+        if !self.started {
+            return 0;
+        }
+        return self.frozen(now_ms).saturating_sub(self.start_ms);
+    }
+
+    pub fn remaining_ms(&self, now_ms: u64) -> u16 {
+        let now_ms = self.frozen(now_ms);
+        self.phase_end_ms().saturating_sub(now_ms) as u16
+    }
+
+    // While paused, time stands still at the moment it was paused
+    fn frozen(&self, now_ms: u64) -> u64 {
+        self.paused_at_ms.unwrap_or(now_ms)
+    }
+
+    fn phase_end_ms(&self) -> u64 {
+        self.phase_start_ms + self.timings.duration_ms(self.phase) as u64
+    }
+
+    fn skip_empty_phases(&mut self) {
+        for _ in 0..PHASE_COUNT {
+            if self.timings.duration_ms(self.phase) != 0 {
+                return;
+            }
+            self.phase = self.phase.next();
+        }
+    }
+}
+
+// Another output breathing along with the engine, e.g. a second LED. It can
+// lag some way behind, like one LED of a wave, and can breathe a preset's
+// pattern of its own on the engine's clock.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Follower {
+    // How far behind the engine, as a percentage of a cycle
+    pub offset_pct: u8,
+    // Percentage of the configured brightness
+    pub brightness_pct: u8,
+    // None to breathe the engine's pattern
+    pub pattern: Option<Preset>,
+}
+
+impl Follower {
+    pub const fn new(offset_pct: u8) -> Self {
+        Follower {
+            offset_pct,
+            brightness_pct: 100,
+            pattern: None,
+        }
+    }
+
+    // Where this follower is in its breath
+    pub fn breath(&self, engine: &BreathingEngine, now_ms: u64) -> Breath {
+        let (timings, position) = match self.pattern {
+            Some(preset) => (Timings::from_preset(preset), engine.breathing_ms(now_ms)),
+            None => (engine.timings(), engine.cycle_position_ms(now_ms) as u64),
+        };
+        let cycle = timings.cycle_ms() as u64;
+        let (phase, level) = match cycle {
+            0 => (Phase::Airless, 0),
+            _ => {
+                let offset = cycle * self.offset_pct.min(100) as u64 / 100;
+                let position = (position % cycle + cycle - offset) % cycle;
+                timings.breath_at(position as u32)
+            }
+        };
+        return Breath {
+            phase,
+            level,
+            paused: engine.is_paused(),
+        };
+    }
+
+    // Scale a brightness from config to this follower's
+    pub fn scale_pct(&self, pct: u8) -> u8 {
+        (pct as u16 * self.brightness_pct.min(100) as u16 / 100) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::{fakes::FakeClock, Clock};
+
+    fn timings(
+        inhale_ms: u16,
+        top_up_ms: u16,
+        hold_ms: u16,
+        exhale_ms: u16,
+        airless_ms: u16,
+    ) -> Timings {
+        Timings {
+            inhale_ms,
+            top_up_ms,
+            hold_ms,
+            exhale_ms,
+            airless_ms,
+        }
+    }
+
+    #[test]
+    fn phases_come_in_order() {
+        let clock = FakeClock::default();
+        let mut engine = BreathingEngine::new(timings(3000, 500, 1000, 4000, 500));
+        assert_eq!(engine.poll(clock.now_ms()), Some(Phase::Inhale));
+        assert_eq!(engine.poll(clock.now_ms()), None);
+
+        let mut entered = [None; 5];
+        for (step, ms) in [3000, 500, 1000, 4000, 500].into_iter().enumerate() {
+            clock.advance(ms - 1);
+            assert_eq!(engine.poll(clock.now_ms()), None);
+            clock.advance(1);
+            entered[step] = engine.poll(clock.now_ms());
+        }
+        assert_eq!(
+            entered,
+            [
+                Some(Phase::TopUp),
+                Some(Phase::Hold),
+                Some(Phase::Exhale),
+                Some(Phase::Airless),
+                Some(Phase::Inhale),
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_length_phases_are_skipped() {
+        let clock = FakeClock::default();
+        let mut engine = BreathingEngine::new(timings(3000, 0, 0, 3000, 0));
+        engine.poll(clock.now_ms());
+        clock.advance(3000);
+        assert_eq!(engine.poll(clock.now_ms()), Some(Phase::Exhale));
+        clock.advance(3000);
+        assert_eq!(engine.poll(clock.now_ms()), Some(Phase::Inhale));
+
+        // Even the first phase
+        let mut engine = BreathingEngine::new(timings(0, 0, 1000, 3000, 0));
+        assert_eq!(engine.poll(0), Some(Phase::Hold));
+    }
+
+    #[test]
+    fn nothing_to_breathe_enters_nothing() {
+        let clock = FakeClock::default();
+        let mut engine = BreathingEngine::new(timings(0, 0, 0, 0, 0));
+        engine.poll(clock.now_ms());
+        for _ in 0..10 {
+            clock.advance(100);
+            assert_eq!(engine.poll(clock.now_ms()), None);
+        }
+    }
+
+    #[test]
+    fn a_late_poll_catches_up() {
+        let clock = FakeClock::default();
+        let mut engine = BreathingEngine::new(timings(3000, 0, 1000, 3000, 1000));
+        engine.poll(clock.now_ms());
+
+        // Two and a bit cycles late, so the phase restarts from now
+        clock.advance(2 * 8000 + 3500);
+        assert_eq!(engine.poll(clock.now_ms()), Some(Phase::Inhale));
+        assert_eq!(engine.remaining_ms(clock.now_ms()), 3000);
+
+        // Less than a cycle late lands in the right phase
+        clock.advance(3000 + 1000 + 500);
+        assert_eq!(engine.poll(clock.now_ms()), Some(Phase::Exhale));
+        assert_eq!(engine.remaining_ms(clock.now_ms()), 2500);
+    }
+
+    #[test]
+    fn new_timings_apply_mid_phase() {
+        let clock = FakeClock::default();
+        let mut engine = BreathingEngine::new(timings(4000, 0, 1000, 4000, 0));
+        engine.poll(clock.now_ms());
+        clock.advance(2000);
+        assert_eq!(engine.level(clock.now_ms()), curve::ONE / 2);
+
+        assert!(engine.set_timings(timings(8000, 0, 1000, 4000, 0)));
+        assert!(!engine.set_timings(timings(8000, 0, 1000, 4000, 0)));
+        assert_eq!(engine.level(clock.now_ms()), curve::ONE / 4);
+        assert_eq!(engine.remaining_ms(clock.now_ms()), 6000);
+
+        // Shorter than the time already spent ends the phase on the next poll
+        engine.set_timings(timings(1000, 0, 4000, 4000, 0));
+        assert_eq!(engine.poll(clock.now_ms()), Some(Phase::Hold));
+    }
+
+    #[test]
+    fn pausing_freezes_the_breath() {
+        let clock = FakeClock::default();
+        let mut engine = BreathingEngine::new(timings(4000, 0, 0, 4000, 0));
+        engine.poll(clock.now_ms());
+        clock.advance(2000);
+        engine.pause(clock.now_ms());
+        clock.advance(7000);
+        assert_eq!(engine.poll(clock.now_ms()), None);
+        assert_eq!(engine.level(clock.now_ms()), curve::ONE / 2);
+        assert_eq!(engine.remaining_ms(clock.now_ms()), 2000);
+
+        engine.resume(clock.now_ms());
+        clock.advance(2000);
+        assert_eq!(engine.poll(clock.now_ms()), Some(Phase::Exhale));
+    }
+
+    // Where a follower is at `now_ms`, behind an engine breathing box-like
+    // timings with a 12 second cycle
+    fn follow(follower: Follower, now_ms: u64) -> (Phase, u32) {
+        let mut engine = BreathingEngine::new(timings(4000, 0, 2000, 4000, 2000));
+        engine.poll(0);
+        engine.poll(now_ms);
+        let breath = follower.breath(&engine, now_ms);
+        return (breath.phase, breath.level);
+    }
+
+    #[test]
+    fn breath_at_finds_the_phase_and_level() {
+        let timings = timings(4000, 0, 2000, 4000, 2000);
+        let cases = [
+            (0, (Phase::Inhale, 0)),
+            (2000, (Phase::Inhale, curve::ONE / 2)),
+            (3999, (Phase::Inhale, curve::ONE * 3999 / 4000)),
+            // Phase boundaries belong to the phase starting there
+            (4000, (Phase::Hold, curve::ONE)),
+            (6000, (Phase::Exhale, curve::ONE)),
+            (9000, (Phase::Exhale, curve::ONE / 4)),
+            (10000, (Phase::Airless, 0)),
+            (11999, (Phase::Airless, 0)),
+            // Past the end of the cycle
+            (12000, (Phase::Airless, 0)),
+            (50000, (Phase::Airless, 0)),
+        ];
+        for (position_ms, breath) in cases {
+            assert_eq!(timings.breath_at(position_ms), breath, "at {}", position_ms);
+        }
+    }
+
+    #[test]
+    fn top_ups_finish_the_inhale() {
+        let peak = curve::ONE * 80 / 100;
+        let timings = timings(3000, 1000, 0, 4000, 0);
+        let cases = [
+            (1500, (Phase::Inhale, peak / 2)),
+            (3000, (Phase::TopUp, peak)),
+            (3500, (Phase::TopUp, peak + (curve::ONE - peak) / 2)),
+            (4000, (Phase::Exhale, curve::ONE)),
+        ];
+        for (position_ms, breath) in cases {
+            assert_eq!(timings.breath_at(position_ms), breath, "at {}", position_ms);
+        }
+    }
+
+    #[test]
+    fn a_follower_lags_behind_and_wraps_round() {
+        // A quarter of a cycle is 3 seconds
+        let follower = Follower::new(25);
+        let cases = [
+            // Still finishing the cycle before
+            (0, (Phase::Exhale, curve::ONE / 4)),
+            (1000, (Phase::Airless, 0)),
+            (3000, (Phase::Inhale, 0)),
+            (5000, (Phase::Inhale, curve::ONE / 2)),
+            (11000, (Phase::Exhale, curve::ONE / 2)),
+        ];
+        for (now_ms, breath) in cases {
+            assert_eq!(follow(follower, now_ms), breath, "at {}", now_ms);
+        }
+    }
+
+    #[test]
+    fn a_follower_can_lag_to_a_phase_boundary() {
+        // Half a cycle behind is the start of the exhale
+        let follower = Follower::new(50);
+        assert_eq!(follow(follower, 0), (Phase::Exhale, curve::ONE));
+        assert_eq!(follow(follower, 4000), (Phase::Airless, 0));
+        assert_eq!(follow(follower, 6000), (Phase::Inhale, 0));
+    }
+
+    #[test]
+    fn a_whole_cycle_or_more_behind_is_in_step() {
+        for offset_pct in [0, 100, 150, u8::MAX] {
+            let follower = Follower::new(offset_pct);
+            for now_ms in [0, 2000, 5000, 9000, 11000] {
+                let mut engine = BreathingEngine::new(timings(4000, 0, 2000, 4000, 2000));
+                engine.poll(0);
+                engine.poll(now_ms);
+                assert_eq!(
+                    follower.breath(&engine, now_ms),
+                    engine.breath(now_ms),
+                    "{}% behind at {}",
+                    offset_pct,
+                    now_ms
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn a_followers_own_pattern_overrides_the_engines() {
+        // Coherent breathing is 5.5 seconds in and 5.5 out
+        let mut follower = Follower::new(0);
+        follower.pattern = Some(Preset::Coherent);
+        let cases = [
+            (2750, (Phase::Inhale, curve::ONE / 2)),
+            (5500, (Phase::Exhale, curve::ONE)),
+            (11000, (Phase::Inhale, 0)),
+        ];
+        for (now_ms, breath) in cases {
+            assert_eq!(follow(follower, now_ms), breath, "at {}", now_ms);
+        }
+
+        // Still lagging by its own cycle, not the engine's
+        follower.offset_pct = 50;
+        assert_eq!(follow(follower, 0), (Phase::Exhale, curve::ONE));
+    }
+}
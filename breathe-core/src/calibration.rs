@@ -0,0 +1,280 @@
+// Interactive pot calibration: the user turns the knob fully one way and
+// presses the button, then fully the other way and presses again. The LED
+// blinks once per cycle for the first step and twice for the second.
+
+// Raw readings at either end of the pot's travel, for this particular unit
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct PotCalibration {
+    pub min: u16,
+    pub max: u16,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum CalibrationStep {
+    TurnToMin,
+    TurnToMax,
+    Done(PotCalibration),
+    // The two ends were too close together to be a real sweep
+    Failed,
+}
+
+const BLINK_MS: u64 = 150;
+const BLINK_PAUSE_MS: u64 = 800;
+const DEBOUNCE_MS: u64 = 50;
+// Anything narrower than this means the knob wasn't actually swept
+const MIN_SPAN: u16 = 1000;
+
+pub struct Calibrator {
+    step: CalibrationStep,
+    lowest: u16,
+    highest: u16,
+    min: u16,
+    was_pressed: bool,
+    last_edge_ms: u64,
+}
+
+impl Calibrator {
+    // The button is usually still held from the boot gesture, so the first
+    // press only counts once it's been let go
+    pub fn new(now_ms: u64) -> Self {
+        Calibrator {
+            step: CalibrationStep::TurnToMin,
+            lowest: u16::MAX,
+            highest: 0,
+            min: 0,
+            was_pressed: true,
+            last_edge_ms: now_ms,
+        }
+    }
+
+    pub fn step(&self) -> CalibrationStep {
+        self.step
+    }
+
+    // Feed in the latest pot reading and button state
+    pub fn poll(&mut self, now_ms: u64, raw: u16, pressed: bool) -> CalibrationStep {
+        self.lowest = self.lowest.min(raw);
+        self.highest = self.highest.max(raw);
+
+        let debounced = now_ms.saturating_sub(self.last_edge_ms) >= DEBOUNCE_MS;
+        if pressed != self.was_pressed && debounced {
+            self.was_pressed = pressed;
+            self.last_edge_ms = now_ms;
+            if pressed {
+                self.confirm();
+            }
+        }
+        return self.step;
+    }
+
+    // Whether the LED should be lit to show which step we're on
+    pub fn led_on(&self, now_ms: u64) -> bool {
+        let blinks = match self.step {
+            CalibrationStep::TurnToMin => 1,
+            CalibrationStep::TurnToMax => 2,
+            _ => return false,
+        };
+        let t = now_ms % (blinks * 2 * BLINK_MS + BLINK_PAUSE_MS);
+        return t < blinks * 2 * BLINK_MS && (t / BLINK_MS).is_multiple_of(2);
+    }
+
+    // Each step keeps the extreme it's looking for from the readings seen
+    // while it was active
+    fn confirm(&mut self) {
+        match self.step {
+            CalibrationStep::TurnToMin => {
+                self.min = self.lowest;
+                self.highest = 0;
+                self.step = CalibrationStep::TurnToMax;
+            }
+            CalibrationStep::TurnToMax => {
+                let max = self.highest;
+                self.step = match max.checked_sub(self.min) {
+                    Some(span) if span >= MIN_SPAN => {
+                        CalibrationStep::Done(PotCalibration { min: self.min, max })
+                    }
+                    _ => CalibrationStep::Failed,
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Polls each (time, raw reading, pressed) in turn, starting at 0 with the
+    // button still held from boot, and returns where calibration got to
+    fn replay(polls: &[(u64, u16, bool)]) -> Calibrator {
+        let mut calibrator = Calibrator::new(0);
+        for (now_ms, raw, pressed) in polls {
+            calibrator.poll(*now_ms, *raw, *pressed);
+        }
+        return calibrator;
+    }
+
+    // Let go of the boot press at `min` and press, then turn to `max` and
+    // press again
+    fn sweep(min: u16, max: u16) -> CalibrationStep {
+        let polls = [
+            (100, min, false),
+            (200, min, false),
+            (300, min, true),
+            (400, min, false),
+            (500, max, false),
+            (600, max, true),
+        ];
+        return replay(&polls).step();
+    }
+
+    #[test]
+    fn each_press_confirms_a_step() {
+        let cases = [
+            (&[][..], CalibrationStep::TurnToMin),
+            // Still held from boot
+            (&[(100, 2000, true)][..], CalibrationStep::TurnToMin),
+            (&[(100, 2000, false)][..], CalibrationStep::TurnToMin),
+            (
+                &[(100, 2000, false), (200, 150, true)][..],
+                CalibrationStep::TurnToMax,
+            ),
+            (
+                &[
+                    (100, 2000, false),
+                    (200, 150, true),
+                    (300, 3900, false),
+                    (400, 3900, true),
+                ][..],
+                CalibrationStep::Done(PotCalibration {
+                    min: 150,
+                    max: 3900,
+                }),
+            ),
+        ];
+        for (polls, step) in cases {
+            assert_eq!(replay(polls).step(), step, "{:?}", polls);
+        }
+    }
+
+    #[test]
+    fn each_step_keeps_its_own_extreme() {
+        // The knob passes through both ends before the first press, but only
+        // readings after it count towards the max
+        let polls = [
+            (100, 4000, false),
+            (200, 100, false),
+            (300, 100, true),
+            (400, 2500, false),
+            (500, 2500, true),
+        ];
+        assert_eq!(
+            replay(&polls).step(),
+            CalibrationStep::Done(PotCalibration {
+                min: 100,
+                max: 2500
+            })
+        );
+    }
+
+    #[test]
+    fn bounces_are_ignored() {
+        let cases = [
+            // Pressed too soon after letting go
+            (
+                &[(100, 150, false), (120, 150, true)][..],
+                CalibrationStep::TurnToMin,
+            ),
+            (
+                &[
+                    (100, 150, false),
+                    (120, 150, true),
+                    (130, 150, false),
+                    (200, 150, true),
+                ][..],
+                CalibrationStep::TurnToMax,
+            ),
+            // Let go and pressed again within the debounce is one press
+            (
+                &[
+                    (100, 150, false),
+                    (200, 150, true),
+                    (210, 150, false),
+                    (220, 150, true),
+                    (230, 3900, false),
+                    (240, 3900, true),
+                ][..],
+                CalibrationStep::TurnToMax,
+            ),
+        ];
+        for (polls, step) in cases {
+            assert_eq!(replay(polls).step(), step, "{:?}", polls);
+        }
+    }
+
+    #[test]
+    fn a_short_sweep_fails() {
+        let cases = [
+            (
+                100,
+                4000,
+                CalibrationStep::Done(PotCalibration {
+                    min: 100,
+                    max: 4000,
+                }),
+            ),
+            (
+                1000,
+                2000,
+                CalibrationStep::Done(PotCalibration {
+                    min: 1000,
+                    max: 2000,
+                }),
+            ),
+            (1000, 1999, CalibrationStep::Failed),
+            (2000, 2000, CalibrationStep::Failed),
+        ];
+        for (min, max, step) in cases {
+            assert_eq!(sweep(min, max), step, "{} to {}", min, max);
+        }
+    }
+
+    #[test]
+    fn the_led_blinks_out_the_step() {
+        let mut calibrator = Calibrator::new(0);
+        // One blink every 1100ms
+        let cases = [
+            (0, true),
+            (149, true),
+            (150, false),
+            (1099, false),
+            (1100, true),
+        ];
+        for (now_ms, on) in cases {
+            assert_eq!(calibrator.led_on(now_ms), on, "first step at {}", now_ms);
+        }
+
+        calibrator.poll(100, 150, false);
+        calibrator.poll(200, 150, true);
+        // Two blinks every 1400ms
+        let cases = [
+            (0, true),
+            (150, false),
+            (300, true),
+            (449, true),
+            (450, false),
+            (1399, false),
+            (1400, true),
+        ];
+        for (now_ms, on) in cases {
+            assert_eq!(calibrator.led_on(now_ms), on, "second step at {}", now_ms);
+        }
+
+        calibrator.poll(300, 3900, false);
+        calibrator.poll(400, 3900, true);
+        for now_ms in [0, 300, 1400] {
+            assert!(!calibrator.led_on(now_ms), "done at {}", now_ms);
+        }
+    }
+}
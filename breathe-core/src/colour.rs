@@ -0,0 +1,116 @@
+use crate::breathing::Phase;
+use crate::config::{Config, SettingName};
+use crate::curve;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    // Part way from this colour to `to`, with `amount` from 0 to `curve::ONE`
+    pub fn blend(&self, to: Rgb, amount: u32) -> Rgb {
+        let amount = amount.min(curve::ONE) as i32;
+        let mix = |from: u8, to: u8| {
+            let from = from as i32;
+            return (from + (to as i32 - from) * amount / curve::ONE as i32) as u8;
+        };
+        return Rgb::new(mix(self.r, to.r), mix(self.g, to.g), mix(self.b, to.b));
+    }
+}
+
+listed_enum! {
+    // Colours a phase can be given
+    pub enum Colour in COLOURS {
+        Red => ("Red", "red"),
+        Amber => ("Amber", "amber"),
+        Yellow => ("Yellow", "yellow"),
+        Green => ("Green", "green"),
+        Cyan => ("Cyan", "cyan"),
+        Blue => ("Blue", "blue"),
+        Purple => ("Purple", "purple"),
+        Pink => ("Pink", "pink"),
+        White => ("White", "white"),
+    }
+}
+
+impl Colour {
+    // Channel levels at full brightness. Brightness comes from the breath, so
+    // every colour has at least one channel fully on.
+    pub fn rgb(&self) -> Rgb {
+        use Colour::*;
+        match self {
+            Red => return Rgb::new(255, 0, 0),
+            Amber => return Rgb::new(255, 110, 0),
+            Yellow => return Rgb::new(255, 200, 0),
+            Green => return Rgb::new(0, 255, 0),
+            Cyan => return Rgb::new(0, 255, 255),
+            Blue => return Rgb::new(0, 0, 255),
+            Purple => return Rgb::new(140, 0, 255),
+            Pink => return Rgb::new(255, 40, 120),
+            White => return Rgb::new(255, 255, 255),
+        }
+    }
+}
+
+// The colour for each phase, from config. Top-ups carry on in the inhale's
+// colour.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct PhaseColours {
+    pub inhale: Colour,
+    pub hold: Colour,
+    pub exhale: Colour,
+    pub airless: Colour,
+}
+
+impl PhaseColours {
+    // The airless colour only shows with a brightness floor above 0, as the
+    // breath is empty through airless pauses
+    pub const fn new() -> Self {
+        PhaseColours {
+            inhale: Colour::Blue,
+            hold: Colour::White,
+            exhale: Colour::Amber,
+            airless: Colour::Red,
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        use SettingName::*;
+        let defaults = PhaseColours::new();
+        let colour = |setting: SettingName, default: Colour| {
+            return config
+                .get(setting)
+                .and_then(Colour::from_index)
+                .unwrap_or(default);
+        };
+        PhaseColours {
+            inhale: colour(InhaleColour, defaults.inhale),
+            hold: colour(HoldColour, defaults.hold),
+            exhale: colour(ExhaleColour, defaults.exhale),
+            airless: colour(AirlessColour, defaults.airless),
+        }
+    }
+
+    pub fn for_phase(&self, phase: Phase) -> Colour {
+        use Phase::*;
+        match phase {
+            Inhale | TopUp => return self.inhale,
+            Hold => return self.hold,
+            Exhale => return self.exhale,
+            Airless => return self.airless,
+        }
+    }
+}
+
+impl Default for PhaseColours {
+    fn default() -> Self {
+        PhaseColours::new()
+    }
+}
@@ -0,0 +1,863 @@
+use core::fmt;
+
+use crate::colour;
+use crate::constants;
+use crate::cue;
+use crate::curve;
+use crate::haptic;
+use crate::preset;
+
+listed_enum! {
+    // How turning the pot changes the current setting
+    pub enum PotMode in POT_MODES {
+        // The setting always follows the knob
+        Absolute => ("Absolute", "absolute"),
+        // The setting ignores the knob until it's turned past the setting's
+        // value, so browsing settings doesn't overwrite them
+        Pickup => ("Pickup", "pickup"),
+        // The setting moves by however far the knob moves
+        Relative => ("Relative", "relative"),
+    }
+}
+
+pub struct Config {
+    pub items: [ConfigItem; 18],
+    // Silences the buzzer without losing its volume. It isn't saved.
+    pub muted: bool,
+    current_item_idx: usize,
+    picked_up: bool,
+    last_position: Option<u16>,
+    // Settings left out of the pot's rotation, one bit per setting's `id`
+    hidden: u32,
+}
+
+#[allow(dead_code)]
+impl Config {
+    pub fn new() -> Self {
+        use SettingName::*;
+        Config {
+            items: [
+                ConfigItem {
+                    setting: InhaleTimeMs,
+                    value: constants::MIN_INHALE_TIME_MS,
+                },
+                ConfigItem {
+                    setting: ExhaleTimeMs,
+                    value: constants::MIN_EXHALE_TIME_MS,
+                },
+                ConfigItem {
+                    setting: HoldTimeMs,
+                    value: constants::DEFAULT_HOLD_TIME_MS,
+                },
+                ConfigItem {
+                    setting: AirlessTimeMs,
+                    value: constants::DEFAULT_AIRLESS_TIME_MS,
+                },
+                ConfigItem {
+                    setting: TopUpTimeMs,
+                    value: constants::MIN_TOP_UP_TIME_MS,
+                },
+                ConfigItem {
+                    setting: BrightnessPct,
+                    value: 100,
+                },
+                ConfigItem {
+                    setting: MinBrightnessPct,
+                    value: 0,
+                },
+                ConfigItem {
+                    setting: AirlessAtFloor,
+                    value: 1,
+                },
+                ConfigItem {
+                    setting: Curve,
+                    value: curve::Curve::Gamma.index(),
+                },
+                ConfigItem {
+                    setting: InhaleColour,
+                    value: colour::Colour::Blue.index(),
+                },
+                ConfigItem {
+                    setting: HoldColour,
+                    value: colour::Colour::White.index(),
+                },
+                ConfigItem {
+                    setting: ExhaleColour,
+                    value: colour::Colour::Amber.index(),
+                },
+                ConfigItem {
+                    setting: AirlessColour,
+                    value: colour::Colour::Red.index(),
+                },
+                ConfigItem {
+                    setting: VolumePct,
+                    value: 50,
+                },
+                ConfigItem {
+                    setting: CueStyle,
+                    value: cue::CueStyle::Tones.index(),
+                },
+                ConfigItem {
+                    setting: HapticStyle,
+                    value: haptic::HapticStyle::Pulse.index(),
+                },
+                ConfigItem {
+                    setting: PotMode,
+                    value: self::PotMode::Pickup.index(),
+                },
+                ConfigItem {
+                    setting: Preset,
+                    value: preset::CUSTOM,
+                },
+            ],
+            muted: false,
+            current_item_idx: 0,
+            picked_up: false,
+            last_position: None,
+            hidden: 0,
+        }
+    }
+
+    // Back to the default settings, keeping the rotation as it is since the
+    // hardware hasn't changed
+    pub fn reset(&mut self) {
+        *self = Config {
+            hidden: self.hidden,
+            ..Config::new()
+        };
+    }
+
+    // Positions are fractions of the pot's travel, up to `POSITION_MAX`
+    pub fn adjust_setting(&mut self, setting: SettingName, position: u16) {
+        for index in 0..self.items.len() {
+            if self.items[index].setting == setting {
+                self.adjust_setting_by_index(index, position);
+            }
+        }
+    }
+
+    pub fn adjust_setting_by_index(&mut self, index: usize, position: u16) {
+        let item = self.items[index];
+        self.set_item_value(index, item.value_for(position));
+    }
+
+    pub fn adjust_current_setting(&mut self, position: u16) {
+        let index = self.current_item_idx;
+        let last_position = self.last_position.replace(position);
+        match self.pot_mode() {
+            PotMode::Absolute => self.adjust_setting_by_index(index, position),
+            PotMode::Pickup => {
+                if !self.picked_up {
+                    let item = self.items[index];
+                    let target = item.value_for(position);
+                    let crossed = match last_position {
+                        Some(last) => (item.value_for(last) < item.value) != (target < item.value),
+                        None => false,
+                    };
+                    self.picked_up = target == item.value || crossed;
+                }
+                if self.picked_up {
+                    self.adjust_setting_by_index(index, position);
+                }
+            }
+            PotMode::Relative => {
+                if let Some(last) = last_position {
+                    let range = self.items[index].setting.range();
+                    let pot_steps =
+                        range.pot_step_at(position) as i16 - range.pot_step_at(last) as i16;
+                    self.turn_setting_by_index(index, pot_steps);
+                }
+            }
+        }
+    }
+
+    // Whether the pot is currently driving the current setting
+    pub fn is_picked_up(&self) -> bool {
+        self.pot_mode() != PotMode::Pickup || self.picked_up
+    }
+
+    pub fn pot_mode(&self) -> PotMode {
+        return self
+            .get(SettingName::PotMode)
+            .and_then(PotMode::from_index)
+            .unwrap_or(PotMode::Pickup);
+    }
+
+    // Move a setting by a number of its steps, stopping at either end
+    pub fn step_setting_by_index(&mut self, index: usize, steps: i16) {
+        let item = self.items[index];
+        let range = item.setting.range();
+        let value = item.value as i32 + steps as i32 * range.step as i32;
+        let value = value.clamp(range.min as i32, range.max as i32) as u16;
+        self.set_item_value(index, range.snap(value));
+    }
+
+    pub fn step_current_setting(&mut self, steps: i16) {
+        self.step_setting_by_index(self.current_item_idx, steps);
+    }
+
+    // Move a setting by a number of pot steps, e.g. for an encoder's detents
+    pub fn turn_setting_by_index(&mut self, index: usize, pot_steps: i16) {
+        let range = self.items[index].setting.range();
+        let steps_per_pot_step = (range.pot_step / range.step) as i16;
+        self.step_setting_by_index(index, pot_steps * steps_per_pot_step);
+    }
+
+    pub fn turn_current_setting(&mut self, pot_steps: i16) {
+        self.turn_setting_by_index(self.current_item_idx, pot_steps);
+    }
+
+    // Dialling in a different preset overwrites every phase duration, which
+    // can then be fine-tuned individually
+    fn set_item_value(&mut self, index: usize, value: u16) {
+        let previous = self.items[index].value;
+        let value = self.range_of(self.items[index].setting).snap(value);
+        self.items[index].value = value;
+
+        if self.items[index].setting == SettingName::Preset && value != previous {
+            if let Some(preset) = preset::Preset::from_index(value) {
+                self.apply_preset(preset);
+            }
+        }
+        self.leave_edited_preset();
+    }
+
+    pub fn apply_preset(&mut self, preset: preset::Preset) {
+        for (setting, value) in preset.durations() {
+            self.store_value(setting, value);
+        }
+        self.store_value(SettingName::Preset, preset.index());
+    }
+
+    // Once a phase has been fine-tuned, the timings are no longer the preset's
+    fn leave_edited_preset(&mut self) {
+        let preset = self
+            .get(SettingName::Preset)
+            .and_then(preset::Preset::from_index);
+        if let Some(preset) = preset {
+            let edited = preset
+                .durations()
+                .into_iter()
+                .any(|(setting, value)| self.get(setting) != Some(value));
+            if edited {
+                self.store_value(SettingName::Preset, preset::CUSTOM);
+            }
+        }
+    }
+
+    // Set an exact value rather than a pot position. Choosing a preset this
+    // way applies it, like dialling it in does.
+    pub fn set(&mut self, setting: SettingName, value: u16) -> Result<(), ConfigError> {
+        self.range_of(setting).check(value)?;
+        if setting == SettingName::Preset {
+            if let Some(preset) = preset::Preset::from_index(value) {
+                self.apply_preset(preset);
+            }
+        }
+        self.store_value(setting, value);
+        self.leave_edited_preset();
+        return Ok(());
+    }
+
+    // A setting's range, narrowed by other settings. The brightness floor has
+    // to stay below the maximum brightness, unless both are 0.
+    pub fn range_of(&self, setting: SettingName) -> SettingRange {
+        let mut range = setting.range();
+        match setting {
+            SettingName::MinBrightnessPct => {
+                let max = self.get(SettingName::BrightnessPct).unwrap_or(range.max);
+                range.max = range.max.min(max.saturating_sub(1));
+            }
+            SettingName::BrightnessPct => {
+                let floor = self.get(SettingName::MinBrightnessPct).unwrap_or(0);
+                if floor > 0 {
+                    range.min = range.min.max(floor + 1);
+                }
+            }
+            _ => {}
+        }
+        return range;
+    }
+
+    // Like `set`, but only stores the value, e.g. when restoring saved settings.
+    // Settings are restored one at a time, so they aren't checked against
+    // each other.
+    pub fn load_value(&mut self, setting: SettingName, value: u16) -> Result<(), ConfigError> {
+        setting.range().check(value)?;
+        self.store_value(setting, value);
+        return Ok(());
+    }
+
+    fn store_value(&mut self, setting: SettingName, value: u16) {
+        for item in &mut self.items {
+            if item.setting == setting {
+                item.value = value;
+            }
+        }
+    }
+
+    // Move on to the next setting in the rotation
+    pub fn next_item(&mut self) {
+        for _ in 0..self.items.len() {
+            self.current_item_idx = (self.current_item_idx + 1) % self.items.len();
+            if !self.is_hidden(self.items[self.current_item_idx].setting) {
+                break;
+            }
+        }
+        self.picked_up = false;
+    }
+
+    // Leave a setting out of the rotation, e.g. one for hardware that isn't
+    // fitted. It's still saved and can be set from the console.
+    pub fn hide(&mut self, setting: SettingName) {
+        self.hidden |= 1 << setting.id();
+    }
+
+    pub fn is_hidden(&self, setting: SettingName) -> bool {
+        self.hidden & (1 << setting.id()) != 0
+    }
+
+    pub fn current_item(&self) -> ConfigItem {
+        self.items[self.current_item_idx]
+    }
+
+    pub fn get(&self, setting: SettingName) -> Option<u16> {
+        for item in self.items {
+            if item.setting == setting {
+                return Some(item.value);
+            }
+        }
+        return None;
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct ConfigItem {
+    pub setting: SettingName,
+    pub value: u16,
+}
+
+listed_enum! {
+    // In the order of their storage ids, so new settings go on the end
+    pub enum SettingName in SETTINGS {
+        InhaleTimeMs => ("Inhale Time MS", "inhale"),
+        ExhaleTimeMs => ("Exhale Time MS", "exhale"),
+        HoldTimeMs => ("Hold Time MS", "hold"),
+        AirlessTimeMs => ("Airless Time MS", "airless"),
+        BrightnessPct => ("Brightness Pct", "brightness"),
+        TopUpTimeMs => ("Top Up Time MS", "topup"),
+        Preset => ("Preset", "preset"),
+        Curve => ("Curve", "curve"),
+        // Dimmest the breath gets, so the rhythm can be followed in the dark
+        MinBrightnessPct => ("Min Brightness Pct", "floor"),
+        // 1 to rest at the floor through airless pauses, 0 to turn off
+        AirlessAtFloor => ("Airless At Floor", "airlessfloor"),
+        // Colours for RGB LEDs, from `colour::COLOURS`
+        InhaleColour => ("Inhale Colour", "inhalecolour"),
+        HoldColour => ("Hold Colour", "holdcolour"),
+        ExhaleColour => ("Exhale Colour", "exhalecolour"),
+        AirlessColour => ("Airless Colour", "airlesscolour"),
+        // Buzzer cues, with 0 volume for silence
+        VolumePct => ("Volume Pct", "volume"),
+        CueStyle => ("Cue Style", "cues"),
+        // How a vibration motor follows the breath
+        HapticStyle => ("Haptic Style", "haptics"),
+        // How turning the pot changes settings, from `POT_MODES`
+        PotMode => ("Pot Mode", "potmode"),
+    }
+}
+
+impl SettingName {
+    pub fn range(&self) -> SettingRange {
+        for (setting, range) in SETTING_RANGES {
+            if setting == *self {
+                return range;
+            }
+        }
+        unreachable!();
+    }
+
+    // Stable identifiers for storage, independent of the order of `items`
+    pub fn id(&self) -> u8 {
+        return self.index() as u8;
+    }
+
+    pub fn from_id(id: u8) -> Option<SettingName> {
+        return SettingName::from_index(id as u16);
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct SettingRange {
+    pub min: u16,
+    pub max: u16,
+    pub step: u16,
+    // How far the setting moves per step of the pot, a multiple of `step`
+    pub pot_step: u16,
+}
+
+const COLOUR_RANGE: SettingRange = SettingRange {
+    min: 0,
+    max: colour::COLOURS.len() as u16 - 1,
+    step: 1,
+    pot_step: 1,
+};
+
+// Valid values for every setting. Values must land on a whole number of
+// steps from the minimum. The pot can be coarser, so the knob isn't too
+// twitchy on settings with fine steps.
+const SETTING_RANGES: [(SettingName, SettingRange); 18] = [
+    (
+        SettingName::InhaleTimeMs,
+        SettingRange {
+            min: constants::MIN_INHALE_TIME_MS,
+            max: constants::MAX_INHALE_TIME_MS,
+            step: constants::TIME_STEP_MS,
+            pot_step: constants::TIME_STEP_MS,
+        },
+    ),
+    (
+        SettingName::ExhaleTimeMs,
+        SettingRange {
+            min: constants::MIN_EXHALE_TIME_MS,
+            max: constants::MAX_EXHALE_TIME_MS,
+            step: constants::TIME_STEP_MS,
+            pot_step: constants::TIME_STEP_MS,
+        },
+    ),
+    (
+        SettingName::HoldTimeMs,
+        SettingRange {
+            min: constants::MIN_HOLD_TIME_MS,
+            max: constants::MAX_HOLD_TIME_MS,
+            step: constants::TIME_STEP_MS,
+            pot_step: constants::TIME_STEP_MS,
+        },
+    ),
+    (
+        SettingName::AirlessTimeMs,
+        SettingRange {
+            min: constants::MIN_AIRLESS_TIME_MS,
+            max: constants::MAX_AIRLESS_TIME_MS,
+            step: constants::TIME_STEP_MS,
+            pot_step: constants::TIME_STEP_MS,
+        },
+    ),
+    (
+        SettingName::TopUpTimeMs,
+        SettingRange {
+            min: constants::MIN_TOP_UP_TIME_MS,
+            max: constants::MAX_TOP_UP_TIME_MS,
+            step: constants::TIME_STEP_MS,
+            pot_step: constants::TIME_STEP_MS,
+        },
+    ),
+    (
+        SettingName::BrightnessPct,
+        SettingRange {
+            min: 0,
+            max: 100,
+            step: 1,
+            pot_step: 5,
+        },
+    ),
+    (
+        SettingName::MinBrightnessPct,
+        SettingRange {
+            min: 0,
+            max: 99,
+            step: 1,
+            pot_step: 1,
+        },
+    ),
+    (
+        SettingName::AirlessAtFloor,
+        SettingRange {
+            min: 0,
+            max: 1,
+            step: 1,
+            pot_step: 1,
+        },
+    ),
+    (
+        SettingName::Curve,
+        SettingRange {
+            min: 0,
+            max: curve::CURVES.len() as u16 - 1,
+            step: 1,
+            pot_step: 1,
+        },
+    ),
+    (SettingName::InhaleColour, COLOUR_RANGE),
+    (SettingName::HoldColour, COLOUR_RANGE),
+    (SettingName::ExhaleColour, COLOUR_RANGE),
+    (SettingName::AirlessColour, COLOUR_RANGE),
+    (
+        SettingName::VolumePct,
+        SettingRange {
+            min: 0,
+            max: 100,
+            step: 1,
+            pot_step: 5,
+        },
+    ),
+    (
+        SettingName::CueStyle,
+        SettingRange {
+            min: 0,
+            max: cue::CUE_STYLES.len() as u16 - 1,
+            step: 1,
+            pot_step: 1,
+        },
+    ),
+    (
+        SettingName::HapticStyle,
+        SettingRange {
+            min: 0,
+            max: haptic::HAPTIC_STYLES.len() as u16 - 1,
+            step: 1,
+            pot_step: 1,
+        },
+    ),
+    (
+        SettingName::PotMode,
+        SettingRange {
+            min: 0,
+            max: POT_MODES.len() as u16 - 1,
+            step: 1,
+            pot_step: 1,
+        },
+    ),
+    (
+        SettingName::Preset,
+        SettingRange {
+            min: 0,
+            max: preset::CUSTOM,
+            step: 1,
+            pot_step: 1,
+        },
+    ),
+];
+
+impl SettingRange {
+    pub fn check(&self, value: u16) -> Result<(), ConfigError> {
+        if value < self.min {
+            return Err(ConfigError::BelowMin(self.min));
+        }
+        if value > self.max {
+            return Err(ConfigError::AboveMax(self.max));
+        }
+        if !(value - self.min).is_multiple_of(self.step) {
+            return Err(ConfigError::OffStep(self.step));
+        }
+        return Ok(());
+    }
+
+    // Nearest valid value
+    pub fn snap(&self, value: u16) -> u16 {
+        let value = value.clamp(self.min, self.max);
+        let steps = (value - self.min + self.step / 2) / self.step;
+        let snapped = self.min + steps * self.step;
+        if snapped > self.max {
+            return snapped - self.step;
+        }
+        return snapped;
+    }
+
+    // Which of the pot's steps a position falls in, counting from `min`
+    pub fn pot_step_at(&self, position: u16) -> u16 {
+        // Round up, so the last step reaches `max` even if it's a short one
+        let pot_steps = (self.max - self.min).div_ceil(self.pot_step) as u32;
+        let position = position as u32;
+        let max = constants::POSITION_MAX as u32;
+        return ((position * pot_steps + max / 2) / max) as u16;
+    }
+
+    // Value at a pot position, rounded to the nearest pot step
+    pub fn value_at(&self, position: u16) -> u16 {
+        let value = self.min as u32 + self.pot_step_at(position) as u32 * self.pot_step as u32;
+        return self.snap(value.min(self.max as u32) as u16);
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ConfigError {
+    BelowMin(u16),
+    AboveMax(u16),
+    OffStep(u16),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ConfigError::*;
+        match self {
+            BelowMin(min) => write!(f, "Must be at least {}", min),
+            AboveMax(max) => write!(f, "Must be at most {}", max),
+            OffStep(step) => write!(f, "Must be in steps of {}", step),
+        }
+    }
+}
+
+impl ConfigItem {
+    pub fn adjust(&mut self, position: u16) {
+        self.value = self.value_for(position);
+    }
+
+    // The value a pot position maps to for this setting
+    pub fn value_for(&self, position: u16) -> u16 {
+        self.setting.range().value_at(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preset::Preset;
+
+    fn preset(config: &Config) -> Option<Preset> {
+        config.get(SettingName::Preset).and_then(Preset::from_index)
+    }
+
+    // Where the pot has to be for a setting to take a value
+    fn position_for(setting: SettingName, value: u16) -> u16 {
+        let range = setting.range();
+        let pot_steps = range.pot_step_at(constants::POSITION_MAX) as u32;
+        let pot_step = ((value - range.min) / range.pot_step) as u32;
+        return (pot_step * constants::POSITION_MAX as u32 / pot_steps) as u16;
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        let config = Config::new();
+        for item in config.items {
+            assert_eq!(config.range_of(item.setting).check(item.value), Ok(()));
+        }
+    }
+
+    #[test]
+    fn keys_and_ids_name_one_setting_each() {
+        for item in Config::new().items {
+            let setting = item.setting;
+            assert_eq!(SettingName::from_key(setting.key()), Some(setting));
+            assert_eq!(SettingName::from_id(setting.id()), Some(setting));
+        }
+        assert_eq!(SettingName::from_key("nope"), None);
+        assert_eq!(SettingName::from_id(200), None);
+
+        // Ids are in saved records, so they can't move
+        assert_eq!(SettingName::InhaleTimeMs.id(), 0);
+        assert_eq!(SettingName::BrightnessPct.id(), 4);
+        assert_eq!(SettingName::Preset.id(), 6);
+        assert_eq!(SettingName::AirlessColour.id(), 13);
+        assert_eq!(SettingName::HapticStyle.id(), 16);
+        assert_eq!(SettingName::PotMode.id(), 17);
+    }
+
+    #[test]
+    fn values_are_checked_against_the_range() {
+        let range = SettingName::InhaleTimeMs.range();
+        assert_eq!(range.check(4000), Ok(()));
+        assert_eq!(range.check(2900), Err(ConfigError::BelowMin(3000)));
+        assert_eq!(range.check(10100), Err(ConfigError::AboveMax(10000)));
+        assert_eq!(range.check(4050), Err(ConfigError::OffStep(100)));
+    }
+
+    #[test]
+    fn snapping_rounds_to_the_nearest_step() {
+        let range = SettingName::InhaleTimeMs.range();
+        assert_eq!(range.snap(4049), 4000);
+        assert_eq!(range.snap(4050), 4100);
+        assert_eq!(range.snap(0), 3000);
+        assert_eq!(range.snap(u16::MAX), 10000);
+    }
+
+    #[test]
+    fn the_pot_reaches_both_ends_of_every_setting() {
+        for item in Config::new().items {
+            let range = item.setting.range();
+            assert_eq!(range.value_at(0), range.min);
+            assert_eq!(range.value_at(constants::POSITION_MAX), range.max);
+        }
+        // Brightness moves in fives on the pot
+        let range = SettingName::BrightnessPct.range();
+        assert_eq!(range.value_at(constants::POSITION_MAX / 2), 50);
+        assert_eq!(
+            range.value_at(position_for(SettingName::BrightnessPct, 35)),
+            35
+        );
+    }
+
+    #[test]
+    fn absolute_mode_follows_the_knob() {
+        let mut config = Config::new();
+        config
+            .set(SettingName::PotMode, PotMode::Absolute.index())
+            .unwrap();
+        config.adjust_current_setting(constants::POSITION_MAX);
+        assert_eq!(config.get(SettingName::InhaleTimeMs), Some(10000));
+        config.adjust_current_setting(0);
+        assert_eq!(config.get(SettingName::InhaleTimeMs), Some(3000));
+    }
+
+    #[test]
+    fn pickup_mode_waits_for_the_knob_to_reach_the_value() {
+        let mut config = Config::new();
+        config.set(SettingName::InhaleTimeMs, 5000).unwrap();
+        let at = |value| position_for(SettingName::InhaleTimeMs, value);
+
+        // Turning below the value leaves it alone
+        config.adjust_current_setting(at(3000));
+        config.adjust_current_setting(at(4000));
+        assert!(!config.is_picked_up());
+        assert_eq!(config.get(SettingName::InhaleTimeMs), Some(5000));
+
+        // Turning past it picks it up, and from then on it follows
+        config.adjust_current_setting(at(6000));
+        assert!(config.is_picked_up());
+        assert_eq!(config.get(SettingName::InhaleTimeMs), Some(6000));
+        config.adjust_current_setting(at(4000));
+        assert_eq!(config.get(SettingName::InhaleTimeMs), Some(4000));
+
+        // Until the next setting is chosen
+        config.next_item();
+        assert!(!config.is_picked_up());
+    }
+
+    #[test]
+    fn pickup_mode_takes_the_value_when_the_knob_lands_on_it() {
+        let mut config = Config::new();
+        config.set(SettingName::InhaleTimeMs, 5000).unwrap();
+        config.adjust_current_setting(position_for(SettingName::InhaleTimeMs, 5000));
+        assert!(config.is_picked_up());
+    }
+
+    #[test]
+    fn relative_mode_moves_by_how_far_the_knob_moves() {
+        let mut config = Config::new();
+        config
+            .set(SettingName::PotMode, PotMode::Relative.index())
+            .unwrap();
+        config.set(SettingName::InhaleTimeMs, 5000).unwrap();
+        let at = |value| position_for(SettingName::InhaleTimeMs, value);
+
+        // The first reading only sets where the knob is
+        config.adjust_current_setting(at(8000));
+        assert_eq!(config.get(SettingName::InhaleTimeMs), Some(5000));
+        config.adjust_current_setting(at(8500));
+        assert_eq!(config.get(SettingName::InhaleTimeMs), Some(5500));
+        config.adjust_current_setting(at(3000));
+        assert_eq!(config.get(SettingName::InhaleTimeMs), Some(3000));
+    }
+
+    #[test]
+    fn steps_stop_at_either_end() {
+        let mut config = Config::new();
+        config.step_current_setting(-3);
+        assert_eq!(config.get(SettingName::InhaleTimeMs), Some(3000));
+        config.step_current_setting(200);
+        assert_eq!(config.get(SettingName::InhaleTimeMs), Some(10000));
+
+        // Brightness turns in fives but steps in ones
+        let brightness = 5;
+        config.set(SettingName::BrightnessPct, 50).unwrap();
+        config.turn_setting_by_index(brightness, -1);
+        assert_eq!(config.get(SettingName::BrightnessPct), Some(45));
+        config.step_setting_by_index(brightness, 1);
+        assert_eq!(config.get(SettingName::BrightnessPct), Some(46));
+    }
+
+    #[test]
+    fn the_floor_stays_below_the_brightness() {
+        let mut config = Config::new();
+        config.set(SettingName::BrightnessPct, 40).unwrap();
+        assert_eq!(
+            config.set(SettingName::MinBrightnessPct, 40),
+            Err(ConfigError::AboveMax(39))
+        );
+        config.set(SettingName::MinBrightnessPct, 20).unwrap();
+        assert_eq!(
+            config.set(SettingName::BrightnessPct, 20),
+            Err(ConfigError::BelowMin(21))
+        );
+
+        // Turning the pot stops short instead
+        config.adjust_setting(SettingName::BrightnessPct, 0);
+        assert_eq!(config.get(SettingName::BrightnessPct), Some(21));
+    }
+
+    #[test]
+    fn next_item_goes_round_every_setting() {
+        let mut config = Config::new();
+        for item in Config::new().items {
+            assert_eq!(config.current_item().setting, item.setting);
+            config.next_item();
+        }
+        assert_eq!(config.current_item().setting, SettingName::InhaleTimeMs);
+    }
+
+    #[test]
+    fn hidden_settings_are_skipped() {
+        let mut config = Config::new();
+        config.hide(SettingName::ExhaleTimeMs);
+        config.hide(SettingName::HoldTimeMs);
+        config.next_item();
+        assert_eq!(config.current_item().setting, SettingName::AirlessTimeMs);
+
+        // But can still be set, and stay hidden through a reset
+        config.set(SettingName::HoldTimeMs, 2000).unwrap();
+        assert_eq!(config.get(SettingName::HoldTimeMs), Some(2000));
+        config.reset();
+        assert_eq!(
+            config.get(SettingName::HoldTimeMs),
+            Some(constants::DEFAULT_HOLD_TIME_MS)
+        );
+        config.next_item();
+        assert_eq!(config.current_item().setting, SettingName::AirlessTimeMs);
+    }
+
+    #[test]
+    fn defaults_are_a_custom_pattern() {
+        let config = Config::new();
+        assert_eq!(config.get(SettingName::Preset), Some(preset::CUSTOM));
+        assert_eq!(preset(&config), None);
+    }
+
+    #[test]
+    fn choosing_a_preset_fills_in_the_phases() {
+        let mut config = Config::new();
+        config
+            .set(SettingName::Preset, Preset::Box.index())
+            .unwrap();
+        assert_eq!(preset(&config), Some(Preset::Box));
+        for (setting, value) in Preset::Box.durations() {
+            assert_eq!(config.get(setting), Some(value));
+        }
+    }
+
+    #[test]
+    fn fine_tuning_a_phase_leaves_the_preset() {
+        let mut config = Config::new();
+        config
+            .set(SettingName::Preset, Preset::Box.index())
+            .unwrap();
+        config.set(SettingName::HoldTimeMs, 4000).unwrap();
+        assert_eq!(preset(&config), Some(Preset::Box));
+        config.set(SettingName::HoldTimeMs, 4100).unwrap();
+        assert_eq!(config.get(SettingName::Preset), Some(preset::CUSTOM));
+
+        // The same by turning the pot on a phase
+        config.apply_preset(Preset::Coherent);
+        config.step_setting_by_index(0, 1);
+        assert_eq!(config.get(SettingName::Preset), Some(preset::CUSTOM));
+
+        // Dialling custom back in leaves the phases alone
+        config.set(SettingName::Preset, preset::CUSTOM).unwrap();
+        assert_eq!(config.get(SettingName::InhaleTimeMs), Some(5600));
+    }
+}
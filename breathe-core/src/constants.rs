@@ -0,0 +1,45 @@
+use crate::pot::{PotFilter, SampleFilter};
+
+// Pot positions are a fraction of its travel, from 0 up to this
+pub const POSITION_MAX: u16 = u16::MAX;
+
+// Potentiometer consts, shared by the device and the simulator so positions
+// line up with the real knob
+pub const POT_READ_COUNT: u16 = 5;
+pub const POT_MIN: u16 = 430;
+pub const POT_MAX: u16 = 3410;
+// Artificially hit the ends of the pot's travel sooner by expanding the deadzone
+pub const POT_DEADZONE: u16 = 200;
+pub const POT_FILTER: PotFilter = PotFilter {
+    samples: SampleFilter::Median,
+    ema_shift: 2,
+    // Small enough to reach every 100 ms step of a phase time
+    hysteresis: 24,
+};
+
+// Config init values
+
+// All phase times move in steps of this much
+pub const TIME_STEP_MS: u16 = 100u16;
+
+pub const MIN_INHALE_TIME_MS: u16 = 3000u16;
+pub const MAX_INHALE_TIME_MS: u16 = 10000u16;
+
+pub const MIN_EXHALE_TIME_MS: u16 = 3000u16;
+pub const MAX_EXHALE_TIME_MS: u16 = 10000u16;
+
+// Holds, top-ups and airless pauses can be skipped entirely, e.g. by presets
+pub const MIN_HOLD_TIME_MS: u16 = 0u16;
+pub const MAX_HOLD_TIME_MS: u16 = 10000u16;
+pub const DEFAULT_HOLD_TIME_MS: u16 = 1000u16;
+
+pub const MIN_AIRLESS_TIME_MS: u16 = 0u16;
+pub const MAX_AIRLESS_TIME_MS: u16 = 10000u16;
+pub const DEFAULT_AIRLESS_TIME_MS: u16 = 500u16;
+
+// Second, short inhale on top of the first, as in a physiological sigh
+pub const MIN_TOP_UP_TIME_MS: u16 = 0u16;
+pub const MAX_TOP_UP_TIME_MS: u16 = 3000u16;
+
+// How long an RGB LED takes to change colour between phases
+pub const COLOUR_CROSSFADE_MS: u64 = 600;
@@ -0,0 +1,259 @@
+use crate::breathing::{Breath, BreathingEngine, Phase};
+use crate::config::{Config, SettingName};
+use crate::curve;
+use crate::hal::ToneOutput;
+use crate::indicator::BreathIndicator;
+
+listed_enum! {
+    // Sounds for following the breath with your eyes closed
+    pub enum CueStyle in CUE_STYLES {
+        // A short tone as each phase starts
+        Tones => ("Tones", "tones"),
+        // The tones, plus a soft pitch that rises with the inhale and falls
+        // with the exhale
+        Sweep => ("Sweep", "sweep"),
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Tone {
+    pub frequency_hz: u16,
+    // 0 to 100
+    pub volume_pct: u8,
+}
+
+const CUE_MS: u64 = 120;
+
+// The sweep's pitch at empty and full, in steps so it isn't retuned on every
+// poll
+const SWEEP_LOW_HZ: u32 = 220;
+const SWEEP_HIGH_HZ: u32 = 440;
+const SWEEP_STEP_HZ: u32 = 5;
+
+// The tone each phase starts with. Top-ups and airless pauses are silent.
+fn cue_hz(phase: Phase) -> Option<u16> {
+    use Phase::*;
+    match phase {
+        Inhale => return Some(523),
+        Hold => return Some(784),
+        Exhale => return Some(392),
+        TopUp | Airless => return None,
+    }
+}
+
+// Plays cues on a buzzer as the breath goes
+pub struct CuePlayer<T: ToneOutput> {
+    pub output: T,
+    pub style: CueStyle,
+    pub volume_pct: u8,
+    pub muted: bool,
+    // The phase cue playing, and when it ends
+    cue: Option<(u16, u64)>,
+    // Last tone sent to the output
+    playing: Option<Tone>,
+}
+
+impl<T: ToneOutput> CuePlayer<T> {
+    pub fn new(output: T) -> Self {
+        CuePlayer {
+            output,
+            style: CueStyle::Tones,
+            volume_pct: 50,
+            muted: false,
+            cue: None,
+            playing: None,
+        }
+    }
+
+    // What should be playing right now, if anything
+    pub fn tone(&mut self, breath: &Breath, now_ms: u64) -> Option<Tone> {
+        if let Some((frequency_hz, until)) = self.cue {
+            if now_ms < until {
+                return self.at_volume(frequency_hz, self.volume_pct);
+            }
+            self.cue = None;
+        }
+
+        if self.style != CueStyle::Sweep || breath.paused {
+            return None;
+        }
+        match breath.phase {
+            Phase::Inhale | Phase::TopUp | Phase::Exhale => {
+                let span = SWEEP_HIGH_HZ - SWEEP_LOW_HZ;
+                let rise = span * (breath.level >> 8) / (curve::ONE >> 8);
+                let frequency_hz = SWEEP_LOW_HZ + rise / SWEEP_STEP_HZ * SWEEP_STEP_HZ;
+                // Quieter than the cues, so they stand out
+                return self.at_volume(frequency_hz as u16, (self.volume_pct / 4).max(1));
+            }
+            _ => return None,
+        }
+    }
+
+    fn at_volume(&self, frequency_hz: u16, volume_pct: u8) -> Option<Tone> {
+        if self.muted || self.volume_pct == 0 {
+            return None;
+        }
+        return Some(Tone {
+            frequency_hz,
+            volume_pct,
+        });
+    }
+}
+
+impl<T: ToneOutput> BreathIndicator for CuePlayer<T> {
+    fn configure(&mut self, config: &Config) {
+        self.volume_pct = config.get(SettingName::VolumePct).unwrap_or(50) as u8;
+        self.style = config
+            .get(SettingName::CueStyle)
+            .and_then(CueStyle::from_index)
+            .unwrap_or(CueStyle::Tones);
+        self.muted = config.muted;
+    }
+
+    // Start the cue for the phase, if it has one
+    fn on_phase(&mut self, phase: Phase, now_ms: u64) {
+        if let Some(frequency_hz) = cue_hz(phase) {
+            self.cue = Some((frequency_hz, now_ms + CUE_MS));
+        }
+    }
+
+    // Only touches the output when the tone changes
+    fn render(&mut self, engine: &BreathingEngine, now_ms: u64) {
+        let tone = self.tone(&engine.breath(now_ms), now_ms);
+        if tone == self.playing {
+            return;
+        }
+        self.playing = tone;
+        match tone {
+            Some(tone) => self.output.play(tone.frequency_hz, tone.volume_pct),
+            None => self.output.stop(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::breathing::Timings;
+    use crate::hal::fakes::FakeTone;
+
+    fn engine() -> BreathingEngine {
+        return BreathingEngine::new(Timings {
+            inhale_ms: 2000,
+            top_up_ms: 500,
+            hold_ms: 1000,
+            exhale_ms: 2000,
+            airless_ms: 500,
+        });
+    }
+
+    // Drives the player through a cycle every 10ms, returning each change
+    // to what the buzzer plays with when it happened
+    fn changes(player: &mut CuePlayer<FakeTone>) -> Vec<(u64, Option<(u16, u8)>)> {
+        let mut engine = engine();
+        let mut changes = Vec::new();
+        let mut last = None;
+        for now_ms in (0..6000).step_by(10) {
+            engine.drive(now_ms, &mut [player]);
+            if player.output.playing != last {
+                last = player.output.playing;
+                changes.push((now_ms, last));
+            }
+        }
+        return changes;
+    }
+
+    fn breath(phase: Phase, level: u32) -> Breath {
+        return Breath {
+            phase,
+            level,
+            paused: false,
+        };
+    }
+
+    #[test]
+    fn each_phase_starts_with_its_tone() {
+        let mut player = CuePlayer::new(FakeTone::default());
+        assert_eq!(
+            changes(&mut player),
+            [
+                (0, Some((523, 50))),
+                (120, None),
+                // Nothing for the top-up at 2000
+                (2500, Some((784, 50))),
+                (2620, None),
+                (3500, Some((392, 50))),
+                (3620, None),
+                // Nor the airless pause at 5500
+            ]
+        );
+    }
+
+    #[test]
+    fn muting_silences_everything() {
+        let mut player = CuePlayer::new(FakeTone::default());
+        player.muted = true;
+        assert_eq!(changes(&mut player), []);
+
+        let mut player = CuePlayer::new(FakeTone::default());
+        player.style = CueStyle::Sweep;
+        player.volume_pct = 0;
+        assert_eq!(changes(&mut player), []);
+
+        // Muting mid-cue cuts it off
+        let mut engine = engine();
+        let mut player = CuePlayer::new(FakeTone::default());
+        engine.drive(0, &mut [&mut player]);
+        assert!(player.output.playing.is_some());
+        player.muted = true;
+        engine.drive(10, &mut [&mut player]);
+        assert_eq!(player.output.playing, None);
+    }
+
+    #[test]
+    fn the_sweep_follows_the_breath() {
+        let mut player = CuePlayer::new(FakeTone::default());
+        player.style = CueStyle::Sweep;
+        let quiet = |frequency_hz| {
+            return Some(Tone {
+                frequency_hz,
+                volume_pct: 12,
+            });
+        };
+        let cases = [
+            (breath(Phase::Inhale, 0), quiet(220)),
+            (breath(Phase::Inhale, curve::ONE / 2), quiet(330)),
+            (breath(Phase::TopUp, curve::ONE * 9 / 10), quiet(415)),
+            (breath(Phase::Exhale, curve::ONE), quiet(440)),
+            (breath(Phase::Exhale, curve::ONE / 4), quiet(275)),
+            // Silent once the breath stops moving
+            (breath(Phase::Hold, curve::ONE), None),
+            (breath(Phase::Airless, 0), None),
+        ];
+        for (breath, tone) in cases {
+            assert_eq!(player.tone(&breath, 0), tone, "{:?}", breath);
+        }
+
+        // Rising in steps through the inhale, and never past the top
+        let mut last = 0;
+        for level in (0..=curve::ONE).step_by(1000) {
+            let tone = player.tone(&breath(Phase::Inhale, level), 0).unwrap();
+            assert!(tone.frequency_hz >= last);
+            assert!(tone.frequency_hz <= SWEEP_HIGH_HZ as u16);
+            assert_eq!(tone.frequency_hz as u32 % SWEEP_STEP_HZ, 0);
+            last = tone.frequency_hz;
+        }
+    }
+
+    #[test]
+    fn cues_play_over_the_sweep() {
+        let mut player = CuePlayer::new(FakeTone::default());
+        player.style = CueStyle::Sweep;
+        let changes = changes(&mut player);
+        assert_eq!(changes[0], (0, Some((523, 50))));
+        assert_eq!(changes[1], (120, Some((230, 12))));
+    }
+}
@@ -0,0 +1,135 @@
+// Curves from how full a breath is to how bright the LED should be. Both ends
+// are fixed point, from 0 to `ONE`, and every curve keeps 0 at 0 and `ONE` at
+// `ONE`.
+
+use crate::config::{Config, SettingName};
+
+pub const ONE: u32 = 1 << 16;
+
+listed_enum! {
+    pub enum Curve in CURVES {
+        Linear => ("Linear", "linear"),
+        // Slow at both ends, like the turn of a breath
+        Sine => ("Sine", "sine"),
+        Cubic => ("Cubic", "cubic"),
+        // Brightness doubles in equal steps, which is roughly how eyes see it
+        Exponential => ("Exponential", "exp"),
+        // CIE 1931 lightness, so equal steps look equally bright
+        Gamma => ("Gamma", "gamma"),
+    }
+}
+
+// sin(x) for x from 0 to pi/2, in 64ths
+#[rustfmt::skip]
+const QUARTER_SINE: [u32; 65] = [
+    0, 1608, 3216, 4821, 6424, 8022, 9616, 11204,
+    12785, 14359, 15924, 17479, 19024, 20557, 22078, 23586,
+    25080, 26558, 28020, 29466, 30893, 32303, 33692, 35062,
+    36410, 37736, 39040, 40320, 41576, 42806, 44011, 45190,
+    46341, 47464, 48559, 49624, 50660, 51665, 52639, 53581,
+    54491, 55368, 56212, 57022, 57798, 58538, 59244, 59914,
+    60547, 61145, 61705, 62228, 62714, 63162, 63572, 63944,
+    64277, 64571, 64827, 65043, 65220, 65358, 65457, 65516,
+    65536,
+];
+
+// The exponential curve covers this many doublings
+const EXPONENTIAL_DOUBLINGS: u32 = 8;
+
+impl Curve {
+    pub fn from_config(config: &Config) -> Curve {
+        return config
+            .get(SettingName::Curve)
+            .and_then(Curve::from_index)
+            .unwrap_or(Curve::Gamma);
+    }
+
+    // Map `x`, from 0 to `ONE`, onto the curve
+    pub fn apply(&self, x: u32) -> u32 {
+        let x = x.min(ONE);
+        use Curve::*;
+        match self {
+            Linear => return x,
+            Sine => return sine(x),
+            Cubic => return cubic(x),
+            Exponential => return exponential(x),
+            Gamma => return gamma(x),
+        }
+    }
+}
+
+// (1 - cos(pi x)) / 2, which is sin(pi x / 2) squared
+fn sine(x: u32) -> u32 {
+    let index = (x >> 10) as usize;
+    let frac = x & 0x3FF;
+    let sin = match QUARTER_SINE.get(index + 1) {
+        Some(next) => {
+            let here = QUARTER_SINE[index];
+            here + (((next - here) * frac) >> 10)
+        }
+        None => ONE,
+    };
+    return ((sin as u64 * sin as u64) >> 16) as u32;
+}
+
+// Ease in and out with a cubic either side of the middle
+fn cubic(x: u32) -> u32 {
+    let one = ONE as u64;
+    if x < ONE / 2 {
+        let x = x as u64;
+        return (4 * x * x * x / (one * one)) as u32;
+    }
+    let rest = 2 * (one - x as u64);
+    return ONE - (rest * rest * rest / (2 * one * one)) as u32;
+}
+
+// (2^(kx) - 1) / (2^k - 1)
+fn exponential(x: u32) -> u32 {
+    let exponent = x * EXPONENTIAL_DOUBLINGS;
+    let whole = exponent >> 16;
+    let frac = (exponent & 0xFFFF) as u64;
+    // 2^frac, within half a percent and exact at both ends
+    let pow2_frac = ONE as u64 + (43024 * frac + 22512 * frac * frac / ONE as u64) / ONE as u64;
+    let pow2 = pow2_frac << whole;
+    let top = (1u64 << EXPONENTIAL_DOUBLINGS) - 1;
+    return ((pow2 - ONE as u64) / top) as u32;
+}
+
+// Luminance for a CIE lightness of 100x
+fn gamma(x: u32) -> u32 {
+    let x = x as u64;
+    let one = ONE as u64;
+    // Below a lightness of 8 the curve is a straight line
+    if x * 100 <= 8 * one {
+        return (x * 1000 / 9033) as u32;
+    }
+    let t = (x * 100 + 16 * one) / 116;
+    return (t * t / one * t / one) as u32;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves_rise_from_0_to_one() {
+        for curve in CURVES {
+            assert_eq!(curve.apply(0), 0, "{}", curve.as_str());
+            assert_eq!(curve.apply(ONE), ONE, "{}", curve.as_str());
+            let mut last = 0;
+            for x in 0..=ONE {
+                let y = curve.apply(x);
+                assert!(y >= last, "{} falls at {}", curve.as_str(), x);
+                assert!(y <= ONE, "{} overshoots at {}", curve.as_str(), x);
+                last = y;
+            }
+        }
+    }
+
+    #[test]
+    fn inputs_past_one_stay_at_one() {
+        for curve in CURVES {
+            assert_eq!(curve.apply(ONE + 1000), ONE);
+        }
+    }
+}
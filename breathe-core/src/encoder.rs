@@ -0,0 +1,103 @@
+// Decodes a rotary encoder's two quadrature channels into detents. Feed it
+// the levels of both channels on every edge of either. Impossible jumps,
+// e.g. from contact bounce, are ignored.
+pub struct QuadratureDecoder {
+    // Quarter-steps between the clicks felt when turning, usually 4
+    pub steps_per_detent: i8,
+    last: u8,
+    count: i8,
+}
+
+// Quarter-steps for each (last state, new state) pair, with states as AB
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+impl QuadratureDecoder {
+    // Both channels are pulled high when the encoder is resting on a detent
+    pub fn new(steps_per_detent: i8) -> Self {
+        QuadratureDecoder {
+            steps_per_detent: steps_per_detent.max(1),
+            last: 0b11,
+            count: 0,
+        }
+    }
+
+    // Returns how many detents the encoder has just turned through, negative
+    // for the other way. Swap the channels to reverse the direction.
+    pub fn update(&mut self, a: bool, b: bool) -> i8 {
+        let state = (a as u8) << 1 | b as u8;
+        self.count += TRANSITIONS[(self.last << 2 | state) as usize];
+        self.last = state;
+
+        let detents = self.count / self.steps_per_detent;
+        self.count -= detents * self.steps_per_detent;
+        return detents;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+
+    // Channel levels as AB, e.g. 0b01 for A low and B high
+    const CLOCKWISE: [u8; 4] = [0b01, 0b00, 0b10, 0b11];
+    const ANTICLOCKWISE: [u8; 4] = [0b10, 0b00, 0b01, 0b11];
+
+    // Feeds in each state from resting on a detent, returning what each
+    // update gave
+    fn feed(states: &[u8]) -> Vec<i8> {
+        let mut decoder = QuadratureDecoder::new(4);
+        return states
+            .iter()
+            .map(|state| decoder.update(state & 0b10 != 0, state & 0b01 != 0))
+            .collect();
+    }
+
+    #[test]
+    fn one_detent_each_way() {
+        assert_eq!(feed(&CLOCKWISE), [0, 0, 0, 1]);
+        assert_eq!(feed(&ANTICLOCKWISE), [0, 0, 0, -1]);
+        assert_eq!(
+            feed(&[CLOCKWISE, CLOCKWISE].concat()),
+            [0, 0, 0, 1, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn bounces_dont_add_steps() {
+        // A falls, bounces back up and falls again before the rest of the click
+        let states = [0b01, 0b11, 0b01, 0b00, 0b10, 0b11];
+        assert_eq!(feed(&states), [0, 0, 0, 0, 0, 1]);
+        // Bouncing on B at the far end of the click
+        let states = [0b01, 0b00, 0b10, 0b11, 0b10, 0b11];
+        assert_eq!(feed(&states), [0, 0, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn impossible_jumps_are_ignored() {
+        let cases = [
+            // Both channels changing at once
+            (&[0b00, 0b11][..], [0, 0]),
+            (&[0b01, 0b10][..], [0, 0]),
+            // Staying put
+            (&[0b11, 0b11][..], [0, 0]),
+        ];
+        for (states, detents) in cases {
+            assert_eq!(feed(states), detents, "{:?}", states);
+        }
+        // And a click afterwards still counts once
+        let states = [0b00, 0b11, 0b01, 0b00, 0b10, 0b11];
+        assert_eq!(feed(&states), [0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn part_of_a_click_gives_nothing() {
+        // Halfway round and back again
+        let states = [0b01, 0b00, 0b01, 0b11];
+        assert_eq!(feed(&states), [0, 0, 0, 0]);
+        // Three quarters round
+        assert_eq!(feed(&CLOCKWISE[..3]), [0, 0, 0]);
+    }
+}
@@ -0,0 +1,195 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// Something that happened on an input, as seen by an interrupt handler
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Input {
+    // A button's pin changed level. `button` is whatever index the firmware
+    // gave it.
+    ButtonEdge { button: u8, pressed: bool },
+    // The encoder turned through some detents, negative for the other way
+    EncoderTurn { detents: i8 },
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct InputEvent {
+    pub at_ms: u64,
+    pub input: Input,
+}
+
+// Only ever read after being overwritten by a real event
+const EMPTY_EVENT: InputEvent = InputEvent {
+    at_ms: 0,
+    input: Input::ButtonEdge {
+        button: 0,
+        pressed: false,
+    },
+};
+
+// Fixed-size, lock-free queue for handing events from interrupts to the main
+// loop. Events go in through its one `Producer` (e.g. in an interrupt handler)
+// and come out of its one `Consumer`, both from `split`. Holds up to N - 1
+// events.
+pub struct EventQueue<const N: usize> {
+    events: UnsafeCell<[InputEvent; N]>,
+    // Next slot to read, only moved by the consumer
+    head: AtomicUsize,
+    // Next slot to write, only moved by the producer
+    tail: AtomicUsize,
+    // Whether the producer and consumer have been handed out
+    split: AtomicBool,
+}
+
+// There's only ever one producer and one consumer, which only touch different
+// slots, handing them over through `head` and `tail`
+unsafe impl<const N: usize> Sync for EventQueue<N> {}
+
+impl<const N: usize> EventQueue<N> {
+    pub const fn new() -> Self {
+        EventQueue {
+            events: UnsafeCell::new([EMPTY_EVENT; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            split: AtomicBool::new(false),
+        }
+    }
+
+    // The queue's producer and consumer. They can only be taken once, so
+    // there's never more than one of either, even for a queue in a `static`.
+    pub fn split(&self) -> Option<(Producer<'_, N>, Consumer<'_, N>)> {
+        if self.split.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        return Some((Producer { queue: self }, Consumer { queue: self }));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire) == self.tail.load(Ordering::Acquire)
+    }
+}
+
+impl<const N: usize> Default for EventQueue<N> {
+    fn default() -> Self {
+        EventQueue::new()
+    }
+}
+
+// The end of an `EventQueue` that events go in
+pub struct Producer<'a, const N: usize> {
+    queue: &'a EventQueue<N>,
+}
+
+impl<const N: usize> Producer<'_, N> {
+    // Hands the event back if the queue is full
+    pub fn push(&mut self, event: InputEvent) -> Result<(), InputEvent> {
+        let queue = self.queue;
+        let tail = queue.tail.load(Ordering::Relaxed);
+        let next = (tail + 1) % N;
+        if next == queue.head.load(Ordering::Acquire) {
+            return Err(event);
+        }
+        let slots = queue.events.get() as *mut InputEvent;
+        // Only the producer writes, and the consumer won't read this slot
+        // until `tail` moves past it
+        unsafe {
+            slots.add(tail).write(event);
+        }
+        queue.tail.store(next, Ordering::Release);
+        return Ok(());
+    }
+}
+
+// The end of an `EventQueue` that events come out of, in the order they went
+// in
+pub struct Consumer<'a, const N: usize> {
+    queue: &'a EventQueue<N>,
+}
+
+impl<const N: usize> Consumer<'_, N> {
+    pub fn pop(&mut self) -> Option<InputEvent> {
+        let queue = self.queue;
+        let head = queue.head.load(Ordering::Relaxed);
+        if head == queue.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let slots = queue.events.get() as *const InputEvent;
+        // The producer has finished with this slot, and won't write it again
+        // until `head` moves past it
+        let event = unsafe { slots.add(head).read() };
+        queue.head.store((head + 1) % N, Ordering::Release);
+        return Some(event);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(at_ms: u64) -> InputEvent {
+        InputEvent {
+            at_ms,
+            input: Input::ButtonEdge {
+                button: 0,
+                pressed: true,
+            },
+        }
+    }
+
+    #[test]
+    fn events_come_out_in_order() {
+        let queue = EventQueue::<8>::new();
+        let (mut producer, mut consumer) = queue.split().unwrap();
+        assert!(consumer.is_empty());
+        for at_ms in 1..=3 {
+            producer.push(edge(at_ms)).unwrap();
+        }
+        assert!(!consumer.is_empty());
+        for at_ms in 1..=3 {
+            assert_eq!(consumer.pop(), Some(edge(at_ms)));
+        }
+        assert_eq!(consumer.pop(), None);
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn a_full_queue_hands_events_back() {
+        let queue = EventQueue::<4>::new();
+        let (mut producer, mut consumer) = queue.split().unwrap();
+        for at_ms in 1..=3 {
+            producer.push(edge(at_ms)).unwrap();
+        }
+        // Full at N - 1
+        assert_eq!(producer.push(edge(4)), Err(edge(4)));
+
+        // Until there's room again
+        assert_eq!(consumer.pop(), Some(edge(1)));
+        producer.push(edge(4)).unwrap();
+        assert_eq!(producer.push(edge(5)), Err(edge(5)));
+    }
+
+    #[test]
+    fn wraps_around_the_end() {
+        let queue = EventQueue::<4>::new();
+        let (mut producer, mut consumer) = queue.split().unwrap();
+        for at_ms in 0..20 {
+            producer.push(edge(at_ms)).unwrap();
+            producer.push(edge(at_ms + 100)).unwrap();
+            assert_eq!(consumer.pop(), Some(edge(at_ms)));
+            assert_eq!(consumer.pop(), Some(edge(at_ms + 100)));
+        }
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn only_splits_once() {
+        let queue = EventQueue::<4>::new();
+        let (mut producer, _consumer) = queue.split().unwrap();
+        assert!(queue.split().is_none());
+        producer.push(edge(1)).unwrap();
+        assert!(!queue.is_empty());
+    }
+}
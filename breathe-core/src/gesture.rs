@@ -0,0 +1,271 @@
+// Turns a button's level over time into gestures. Feed it every edge as it
+// happens and poll it regularly in between, so gestures that complete on a
+// timeout (a single click, a hold) are noticed without blocking.
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ButtonEvent {
+    Click,
+    DoubleClick,
+    // Released after being held for a while
+    LongPress,
+    // Still held after a longer while
+    Hold,
+    // Still held after a hold, repeating until released
+    HoldRepeat,
+}
+
+impl ButtonEvent {
+    pub fn as_str<'a>(&self) -> &'a str {
+        use ButtonEvent::*;
+        match self {
+            Click => return "Click",
+            DoubleClick => return "Double Click",
+            LongPress => return "Long Press",
+            Hold => return "Hold",
+            HoldRepeat => return "Hold Repeat",
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct GestureTimings {
+    // Edges this soon after the last one are contact bounce
+    pub debounce_ms: u16,
+    // Longest gap between the clicks of a double click
+    pub double_click_ms: u16,
+    pub long_press_ms: u16,
+    pub hold_ms: u16,
+    pub hold_repeat_ms: u16,
+}
+
+impl GestureTimings {
+    pub fn new() -> Self {
+        GestureTimings {
+            debounce_ms: 30,
+            double_click_ms: 300,
+            long_press_ms: 800,
+            hold_ms: 2000,
+            hold_repeat_ms: 500,
+        }
+    }
+}
+
+impl Default for GestureTimings {
+    fn default() -> Self {
+        GestureTimings::new()
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+enum State {
+    Idle,
+    Pressed {
+        since: u64,
+        next_hold: u64,
+        holding: bool,
+    },
+    // Released after a click, waiting to see if another follows
+    Released {
+        at: u64,
+    },
+    SecondPress {
+        since: u64,
+    },
+}
+
+pub struct GestureRecognizer {
+    pub timings: GestureTimings,
+    state: State,
+    pressed: bool,
+    // Latest level reported, which may still be bouncing
+    level: bool,
+    last_edge_ms: Option<u64>,
+}
+
+impl GestureRecognizer {
+    pub fn new(timings: GestureTimings) -> Self {
+        GestureRecognizer {
+            timings,
+            state: State::Idle,
+            pressed: false,
+            level: false,
+            last_edge_ms: None,
+        }
+    }
+
+    // Whether the button is pressed, once debounced
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    // Report the button's level at `now_ms`, from an edge interrupt or a poll.
+    // Returns a gesture if one just completed.
+    pub fn update(&mut self, now_ms: u64, pressed: bool) -> Option<ButtonEvent> {
+        self.level = pressed;
+        let settled = match self.last_edge_ms {
+            Some(last) => now_ms.saturating_sub(last) >= self.timings.debounce_ms as u64,
+            None => true,
+        };
+        if pressed != self.pressed && settled {
+            self.pressed = pressed;
+            self.last_edge_ms = Some(now_ms);
+            return self.edge(now_ms, pressed);
+        }
+        return self.timeout(now_ms);
+    }
+
+    // Like `update` with the last level reported, for when only edges are
+    // reported as they happen
+    pub fn poll(&mut self, now_ms: u64) -> Option<ButtonEvent> {
+        self.update(now_ms, self.level)
+    }
+
+    fn edge(&mut self, now_ms: u64, pressed: bool) -> Option<ButtonEvent> {
+        let timings = self.timings;
+        let (state, event) = match (self.state, pressed) {
+            (State::Idle, true) => (self.pressed_at(now_ms), None),
+            // Too late for a double click, so the last click stands alone
+            (State::Released { at }, true)
+                if now_ms.saturating_sub(at) > timings.double_click_ms as u64 =>
+            {
+                (self.pressed_at(now_ms), Some(ButtonEvent::Click))
+            }
+            (State::Released { .. }, true) => (State::SecondPress { since: now_ms }, None),
+            (State::Pressed { holding: true, .. }, false) => (State::Idle, None),
+            (State::Pressed { since, .. }, false)
+                if now_ms.saturating_sub(since) >= timings.long_press_ms as u64 =>
+            {
+                (State::Idle, Some(ButtonEvent::LongPress))
+            }
+            (State::Pressed { .. }, false) => (State::Released { at: now_ms }, None),
+            (State::SecondPress { .. }, false) => (State::Idle, Some(ButtonEvent::DoubleClick)),
+            (state, _) => (state, None),
+        };
+        self.state = state;
+        return event;
+    }
+
+    fn timeout(&mut self, now_ms: u64) -> Option<ButtonEvent> {
+        let timings = self.timings;
+        match self.state {
+            State::Released { at }
+                if now_ms.saturating_sub(at) > timings.double_click_ms as u64 =>
+            {
+                self.state = State::Idle;
+                return Some(ButtonEvent::Click);
+            }
+            State::Pressed {
+                since,
+                next_hold,
+                holding,
+            } if now_ms >= next_hold => {
+                self.state = State::Pressed {
+                    since,
+                    next_hold: now_ms + timings.hold_repeat_ms as u64,
+                    holding: true,
+                };
+                if holding {
+                    return Some(ButtonEvent::HoldRepeat);
+                }
+                return Some(ButtonEvent::Hold);
+            }
+            // Held too long for a double click, so it's a click then a press
+            State::SecondPress { since }
+                if now_ms.saturating_sub(since) >= timings.long_press_ms as u64 =>
+            {
+                self.state = self.pressed_at(since);
+                return Some(ButtonEvent::Click);
+            }
+            _ => return None,
+        }
+    }
+
+    fn pressed_at(&self, now_ms: u64) -> State {
+        State::Pressed {
+            since: now_ms,
+            next_hold: now_ms + self.timings.hold_ms as u64,
+            holding: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    // Plays the button's edges against the default timings, polling every
+    // millisecond in between, and returns each gesture with when it came
+    fn replay(edges: &[(u64, bool)], until_ms: u64) -> Vec<(u64, ButtonEvent)> {
+        let mut recognizer = GestureRecognizer::new(GestureTimings::new());
+        let mut events = Vec::new();
+        for now_ms in 0..=until_ms {
+            let event = match edges.iter().find(|(at, _)| *at == now_ms) {
+                Some((_, pressed)) => recognizer.update(now_ms, *pressed),
+                None => recognizer.poll(now_ms),
+            };
+            if let Some(event) = event {
+                events.push((now_ms, event));
+            }
+        }
+        return events;
+    }
+
+    #[test]
+    fn a_bouncing_press_is_one_click() {
+        let edges = [
+            (100, true),
+            (105, false),
+            (110, true),
+            (200, false),
+            (203, true),
+            (207, false),
+        ];
+        // Once it's too late for a double click
+        assert_eq!(replay(&edges, 3000), [(501, ButtonEvent::Click)]);
+    }
+
+    #[test]
+    fn two_quick_clicks_are_a_double_click() {
+        let edges = [(100, true), (200, false), (350, true), (450, false)];
+        assert_eq!(replay(&edges, 3000), [(450, ButtonEvent::DoubleClick)]);
+
+        // Any slower and they're two clicks
+        let edges = [(100, true), (200, false), (550, true), (650, false)];
+        assert_eq!(
+            replay(&edges, 3000),
+            [(501, ButtonEvent::Click), (951, ButtonEvent::Click)]
+        );
+    }
+
+    #[test]
+    fn a_long_press_comes_on_release() {
+        let edges = [(100, true), (1000, false)];
+        assert_eq!(replay(&edges, 3000), [(1000, ButtonEvent::LongPress)]);
+    }
+
+    #[test]
+    fn holding_repeats_until_released() {
+        let edges = [(100, true), (3200, false)];
+        assert_eq!(
+            replay(&edges, 5000),
+            [
+                (2100, ButtonEvent::Hold),
+                (2600, ButtonEvent::HoldRepeat),
+                (3100, ButtonEvent::HoldRepeat),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_long_second_press_is_a_click_then_a_press() {
+        let edges = [(100, true), (200, false), (350, true), (2000, false)];
+        assert_eq!(
+            replay(&edges, 3000),
+            [(1150, ButtonEvent::Click), (2000, ButtonEvent::LongPress)]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+// What the core needs from a board. The firmware implements these over the
+// ESP32 peripherals, and anything else (tests, a simulator) can fake them.
+
+// Milliseconds since some fixed point, never going backwards
+pub trait Clock {
+    fn now_ms(&self) -> u64;
+}
+
+// A raw ADC reading, e.g. from the potentiometer
+pub trait AnalogInput {
+    fn read_raw(&mut self) -> u16;
+}
+
+pub trait ButtonSource {
+    fn is_pressed(&mut self) -> bool;
+}
+
+// A PWM output, with duty in raw counts from 0 (off) to `max_duty` (fully
+// on). Fades are stepped by the caller, which has the whole resolution to
+// work with.
+pub trait PwmFader {
+    fn max_duty(&self) -> u32;
+    fn set_duty(&mut self, duty: u32);
+
+    fn set_duty_pct(&mut self, pct: u8) {
+        let duty = self.max_duty() as u64 * pct.min(100) as u64 / 100;
+        self.set_duty(duty as u32);
+    }
+}
+
+// Something that can play a tone, e.g. a piezo buzzer. Volume is 0 to 100.
+pub trait ToneOutput {
+    fn play(&mut self, frequency_hz: u16, volume_pct: u8);
+    fn stop(&mut self);
+}
+
+// Stand-ins for the board, for host tests
+#[cfg(test)]
+pub mod fakes {
+    use core::cell::Cell;
+
+    use super::{AnalogInput, Clock, PwmFader, ToneOutput};
+
+    // A clock that only moves when told to, starting from 0
+    #[derive(Default)]
+    pub struct FakeClock {
+        now_ms: Cell<u64>,
+    }
+
+    impl FakeClock {
+        pub fn advance(&self, ms: u64) {
+            self.now_ms.set(self.now_ms.get() + ms);
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now_ms(&self) -> u64 {
+            self.now_ms.get()
+        }
+    }
+
+    // An ADC that plays back a list of readings, over and over
+    pub struct FakeAdc<'a> {
+        pub readings: &'a [u16],
+        next: usize,
+    }
+
+    impl<'a> FakeAdc<'a> {
+        pub fn new(readings: &'a [u16]) -> Self {
+            FakeAdc { readings, next: 0 }
+        }
+    }
+
+    impl AnalogInput for FakeAdc<'_> {
+        fn read_raw(&mut self) -> u16 {
+            let reading = self.readings[self.next % self.readings.len()];
+            self.next += 1;
+            reading
+        }
+    }
+
+    // A PWM output that keeps the last duty it was given, and counts writes
+    pub struct FakeFader {
+        pub max_duty: u32,
+        pub duty: u32,
+        pub writes: u32,
+    }
+
+    impl FakeFader {
+        pub fn new(max_duty: u32) -> Self {
+            FakeFader {
+                max_duty,
+                duty: 0,
+                writes: 0,
+            }
+        }
+    }
+
+    impl PwmFader for FakeFader {
+        fn max_duty(&self) -> u32 {
+            self.max_duty
+        }
+
+        fn set_duty(&mut self, duty: u32) {
+            self.duty = duty;
+            self.writes += 1;
+        }
+    }
+
+    // A buzzer that keeps the frequency and volume it's playing, if any
+    #[derive(Default)]
+    pub struct FakeTone {
+        pub playing: Option<(u16, u8)>,
+    }
+
+    impl ToneOutput for FakeTone {
+        fn play(&mut self, frequency_hz: u16, volume_pct: u8) {
+            self.playing = Some((frequency_hz, volume_pct));
+        }
+
+        fn stop(&mut self) {
+            self.playing = None;
+        }
+    }
+}
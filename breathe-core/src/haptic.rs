@@ -0,0 +1,183 @@
+use crate::breathing::{Breath, BreathingEngine, Phase};
+use crate::config::{Config, SettingName};
+use crate::curve;
+use crate::hal::PwmFader;
+use crate::indicator::BreathIndicator;
+use crate::led::DutyCache;
+
+listed_enum! {
+    // How a vibration motor follows the breath
+    pub enum HapticStyle in HAPTIC_STYLES {
+        Off => ("Off", "off"),
+        // A short buzz as each phase starts
+        Pulse => ("Pulse", "pulse"),
+        // Vibration that swells with the inhale and fades with the exhale
+        Ramp => ("Ramp", "ramp"),
+    }
+}
+
+const PULSE_MS: u64 = 150;
+
+// Below this a small motor stalls rather than turning slowly, so ramps start
+// here
+const MOTOR_START_PCT: u8 = 30;
+
+// A vibration motor switched by a transistor on a PWM output
+pub struct HapticMotor<F: PwmFader> {
+    pub motor: F,
+    pub style: HapticStyle,
+    // How hard it vibrates at most, from 0 to 100
+    pub strength_pct: u8,
+    // When the pulse for the last phase change ends
+    pulse_until_ms: Option<u64>,
+    duty: DutyCache,
+}
+
+impl<F: PwmFader> HapticMotor<F> {
+    pub fn new(motor: F) -> Self {
+        HapticMotor {
+            motor,
+            style: HapticStyle::Pulse,
+            strength_pct: 100,
+            pulse_until_ms: None,
+            duty: DutyCache::default(),
+        }
+    }
+
+    // How hard it should be vibrating right now, from 0 to 100
+    pub fn intensity_pct(&self, breath: &Breath, now_ms: u64) -> u8 {
+        let strength = self.strength_pct.min(100);
+        if breath.paused || strength == 0 {
+            return 0;
+        }
+        match self.style {
+            HapticStyle::Off => return 0,
+            HapticStyle::Pulse => match self.pulse_until_ms {
+                Some(until) if now_ms < until => return strength,
+                _ => return 0,
+            },
+            HapticStyle::Ramp if breath.level == 0 => return 0,
+            HapticStyle::Ramp => {
+                let from = MOTOR_START_PCT.min(strength) as u32;
+                let span = strength as u32 - from;
+                return (from + span * (breath.level >> 8) / (curve::ONE >> 8)) as u8;
+            }
+        }
+    }
+}
+
+impl<F: PwmFader> BreathIndicator for HapticMotor<F> {
+    fn configure(&mut self, config: &Config) {
+        self.style = config
+            .get(SettingName::HapticStyle)
+            .and_then(HapticStyle::from_index)
+            .unwrap_or(HapticStyle::Pulse);
+    }
+
+    fn on_phase(&mut self, _phase: Phase, now_ms: u64) {
+        self.pulse_until_ms = Some(now_ms + PULSE_MS);
+    }
+
+    fn render(&mut self, engine: &BreathingEngine, now_ms: u64) {
+        let pct = self.intensity_pct(&engine.breath(now_ms), now_ms);
+        let duty = (self.motor.max_duty() as u64 * pct as u64 / 100) as u32;
+        self.duty.write(&mut self.motor, duty);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breathing::Timings;
+    use crate::hal::fakes::FakeFader;
+
+    fn breath(phase: Phase, level: u32) -> Breath {
+        return Breath {
+            phase,
+            level,
+            paused: false,
+        };
+    }
+
+    fn motor(style: HapticStyle) -> HapticMotor<FakeFader> {
+        let mut motor = HapticMotor::new(FakeFader::new(1000));
+        motor.style = style;
+        return motor;
+    }
+
+    #[test]
+    fn a_pulse_buzzes_as_each_phase_starts() {
+        let mut engine = BreathingEngine::new(Timings {
+            inhale_ms: 2000,
+            top_up_ms: 0,
+            hold_ms: 1000,
+            exhale_ms: 2000,
+            airless_ms: 0,
+        });
+        let mut motor = motor(HapticStyle::Pulse);
+        let cases = [
+            (0, 1000),
+            (149, 1000),
+            (150, 0),
+            (1999, 0),
+            (2000, 1000),
+            (2150, 0),
+        ];
+        for (now_ms, duty) in cases {
+            engine.drive(now_ms, &mut [&mut motor]);
+            assert_eq!(motor.motor.duty, duty, "at {}", now_ms);
+        }
+    }
+
+    #[test]
+    fn a_ramp_follows_the_breath() {
+        let mut motor = motor(HapticStyle::Ramp);
+        let cases = [
+            (breath(Phase::Inhale, 0), 0),
+            // Starting from where the motor turns
+            (breath(Phase::Inhale, 1 << 8), 30),
+            (breath(Phase::Inhale, curve::ONE / 2), 65),
+            (breath(Phase::Hold, curve::ONE), 100),
+            (breath(Phase::Exhale, curve::ONE / 4), 47),
+        ];
+        for (breath, pct) in cases {
+            assert_eq!(motor.intensity_pct(&breath, 0), pct, "{:?}", breath);
+        }
+
+        // Scaled into a gentler strength
+        motor.strength_pct = 60;
+        assert_eq!(
+            motor.intensity_pct(&breath(Phase::Inhale, curve::ONE / 2), 0),
+            45
+        );
+        assert_eq!(motor.intensity_pct(&breath(Phase::Hold, curve::ONE), 0), 60);
+        // Or all of a strength too weak to ramp
+        motor.strength_pct = 20;
+        assert_eq!(
+            motor.intensity_pct(&breath(Phase::Inhale, curve::ONE / 2), 0),
+            20
+        );
+    }
+
+    #[test]
+    fn sometimes_it_doesnt_vibrate_at_all() {
+        let full = breath(Phase::Hold, curve::ONE);
+        let mut off = motor(HapticStyle::Off);
+        off.on_phase(Phase::Hold, 0);
+        assert_eq!(off.intensity_pct(&full, 0), 0);
+
+        for style in [HapticStyle::Pulse, HapticStyle::Ramp] {
+            let mut motor = motor(style);
+            motor.on_phase(Phase::Hold, 0);
+            motor.strength_pct = 0;
+            assert_eq!(motor.intensity_pct(&full, 0), 0, "{:?} at 0%", style);
+
+            motor.strength_pct = 100;
+            let paused = Breath {
+                paused: true,
+                ..full
+            };
+            assert_eq!(motor.intensity_pct(&paused, 0), 0, "{:?} paused", style);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use core::fmt::Write;
+
+use crate::breathing::{BreathingEngine, Phase};
+use crate::config::Config;
+
+// Anything that follows the breath, e.g. an LED, a buzzer, a vibration motor
+// or a log. The engine fans out to a list of these, so a new output only has
+// to be added to the list.
+pub trait BreathIndicator {
+    // Pick up settings, e.g. brightness or volume. Called every time round the
+    // loop, so changes take effect mid-phase.
+    fn configure(&mut self, _config: &Config) {}
+    // A phase has just started
+    fn on_phase(&mut self, _phase: Phase, _now_ms: u64) {}
+    // Show the breath as it is right now. Called every time round the loop,
+    // so outputs step themselves rather than fading in hardware.
+    fn render(&mut self, engine: &BreathingEngine, now_ms: u64);
+}
+
+// A set of the same output, e.g. extra LEDs, counts as one indicator
+impl<I: BreathIndicator, const N: usize> BreathIndicator for [I; N] {
+    fn configure(&mut self, config: &Config) {
+        for indicator in self.iter_mut() {
+            indicator.configure(config);
+        }
+    }
+
+    fn on_phase(&mut self, phase: Phase, now_ms: u64) {
+        for indicator in self.iter_mut() {
+            indicator.on_phase(phase, now_ms);
+        }
+    }
+
+    fn render(&mut self, engine: &BreathingEngine, now_ms: u64) {
+        for indicator in self.iter_mut() {
+            indicator.render(engine, now_ms);
+        }
+    }
+}
+
+// Writes each phase's name as it starts, e.g. to a serial port
+pub struct LogIndicator<W: Write> {
+    pub out: W,
+}
+
+impl<W: Write> BreathIndicator for LogIndicator<W> {
+    fn on_phase(&mut self, phase: Phase, _now_ms: u64) {
+        // Nowhere to report a failed write, and the breath carries on anyway
+        let _ = writeln!(self.out, "{}", phase.as_str());
+    }
+
+    fn render(&mut self, _engine: &BreathingEngine, _now_ms: u64) {}
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::{string::String, vec::Vec};
+
+    use super::*;
+    use crate::breathing::Timings;
+
+    // Keeps everything it's given, to check against
+    #[derive(Default)]
+    struct Recorder {
+        configured: bool,
+        phases: Vec<(Phase, u64)>,
+        renders: Vec<(Phase, u32)>,
+    }
+
+    impl BreathIndicator for Recorder {
+        fn configure(&mut self, _config: &Config) {
+            self.configured = true;
+        }
+
+        fn on_phase(&mut self, phase: Phase, now_ms: u64) {
+            self.phases.push((phase, now_ms));
+        }
+
+        fn render(&mut self, engine: &BreathingEngine, now_ms: u64) {
+            self.renders.push((engine.phase(), engine.level(now_ms)));
+        }
+    }
+
+    fn engine() -> BreathingEngine {
+        return BreathingEngine::new(Timings {
+            inhale_ms: 2000,
+            top_up_ms: 500,
+            hold_ms: 1000,
+            exhale_ms: 2000,
+            airless_ms: 500,
+        });
+    }
+
+    // Drive through one full cycle and into the next, every half second
+    fn breathe(engine: &mut BreathingEngine, indicators: &mut [&mut dyn BreathIndicator]) {
+        for now in (0..=6000).step_by(500) {
+            engine.drive(now, indicators);
+        }
+    }
+
+    #[test]
+    fn an_indicator_follows_a_full_cycle() {
+        let mut engine = engine();
+        let mut recorder = Recorder::default();
+        breathe(&mut engine, &mut [&mut recorder]);
+
+        assert_eq!(
+            recorder.phases,
+            [
+                (Phase::Inhale, 0),
+                (Phase::TopUp, 2000),
+                (Phase::Hold, 2500),
+                (Phase::Exhale, 3500),
+                (Phase::Airless, 5500),
+                (Phase::Inhale, 6000),
+            ]
+        );
+        // Rendered every time round, after hearing of the phase
+        assert_eq!(recorder.renders.len(), 13);
+        assert_eq!(recorder.renders[0], (Phase::Inhale, 0));
+        assert_eq!(recorder.renders[4].0, Phase::TopUp);
+        assert_eq!(recorder.renders[12], (Phase::Inhale, 0));
+    }
+
+    #[test]
+    fn the_log_names_each_phase() {
+        let mut engine = engine();
+        let mut log = LogIndicator { out: String::new() };
+        breathe(&mut engine, &mut [&mut log]);
+        assert_eq!(log.out, "Inhale\nTop Up\nHold\nExhale\nAirless\nInhale\n");
+    }
+
+    #[test]
+    fn a_set_passes_everything_to_each() {
+        let mut engine = engine();
+        let mut set = [Recorder::default(), Recorder::default()];
+        set.configure(&Config::new());
+        breathe(&mut engine, &mut [&mut set]);
+
+        for recorder in set.iter() {
+            assert!(recorder.configured);
+            assert_eq!(recorder.phases.len(), 6);
+            assert_eq!(recorder.renders.len(), 13);
+        }
+    }
+
+    #[test]
+    fn every_indicator_gets_the_same_breath() {
+        let mut engine = engine();
+        let mut first = Recorder::default();
+        let mut second = Recorder::default();
+        breathe(&mut engine, &mut [&mut first, &mut second]);
+
+        assert_eq!(first.phases, second.phases);
+        assert_eq!(first.renders, second.renders);
+        assert!(!first.renders.is_empty());
+    }
+}
@@ -0,0 +1,388 @@
+use crate::breathing::{BreathingEngine, Follower, Phase};
+use crate::colour::{PhaseColours, Rgb};
+use crate::config::{Config, SettingName};
+use crate::constants;
+use crate::curve::{self, Curve};
+use crate::hal::PwmFader;
+use crate::indicator::BreathIndicator;
+
+// The brightness floor and maximum from config, as percentages
+fn brightness_pct(config: &Config) -> (u8, u8) {
+    let floor = config.get(SettingName::MinBrightnessPct).unwrap_or(0) as u8;
+    let max = config.get(SettingName::BrightnessPct).unwrap_or(100) as u8;
+    return (floor, max);
+}
+
+fn airless_at_floor(config: &Config) -> bool {
+    return config.get(SettingName::AirlessAtFloor) != Some(0);
+}
+
+// The last duty written to a PWM output, so outputs can step every time round
+// the loop without touching the hardware when nothing changed
+#[derive(Default)]
+pub struct DutyCache {
+    duty: Option<u32>,
+}
+
+impl DutyCache {
+    pub fn write(&mut self, fader: &mut impl PwmFader, duty: u32) {
+        if self.duty != Some(duty) {
+            self.duty = Some(duty);
+            fader.set_duty(duty);
+        }
+    }
+}
+
+pub struct BreathingLed<F: PwmFader> {
+    pub fader: F,
+    // Duty range the breath moves through, in the fader's raw counts
+    pub min_duty: u32,
+    pub max_duty: u32,
+    // How the breath maps onto brightness
+    pub curve: Curve,
+    // Rest at `min_duty` through airless pauses, rather than turning off
+    pub airless_at_floor: bool,
+    // Breathe behind the engine, or a pattern of its own, rather than with it
+    pub follower: Option<Follower>,
+    duty: DutyCache,
+}
+
+impl<F: PwmFader> BreathingLed<F> {
+    pub fn new(fader: F) -> Self {
+        let max_duty = fader.max_duty();
+        BreathingLed {
+            fader,
+            min_duty: 0,
+            max_duty,
+            curve: Curve::Gamma,
+            airless_at_floor: true,
+            follower: None,
+            duty: DutyCache::default(),
+        }
+    }
+
+    // Set the duty range from brightness percentages, e.g. from config
+    pub fn set_brightness_pct(&mut self, min_pct: u8, max_pct: u8) {
+        let full = self.fader.max_duty() as u64;
+        self.min_duty = (full * min_pct.min(100) as u64 / 100) as u32;
+        self.max_duty = (full * max_pct.min(100) as u64 / 100) as u32;
+    }
+
+    fn set_duty(&mut self, duty: u32) {
+        self.duty.write(&mut self.fader, duty);
+    }
+}
+
+impl<F: PwmFader> BreathIndicator for BreathingLed<F> {
+    // A follower's brightness is scaled down from the configured one
+    fn configure(&mut self, config: &Config) {
+        let (floor, max) = brightness_pct(config);
+        match self.follower {
+            Some(follower) => {
+                self.set_brightness_pct(follower.scale_pct(floor), follower.scale_pct(max))
+            }
+            None => self.set_brightness_pct(floor, max),
+        }
+        self.curve = Curve::from_config(config);
+        self.airless_at_floor = airless_at_floor(config);
+    }
+
+    fn render(&mut self, engine: &BreathingEngine, now_ms: u64) {
+        let breath = match self.follower {
+            Some(follower) => follower.breath(engine, now_ms),
+            None => engine.breath(now_ms),
+        };
+        if breath.phase == Phase::Airless && !self.airless_at_floor {
+            self.set_duty(0);
+            return;
+        }
+        let range = self.max_duty.saturating_sub(self.min_duty) as u64;
+        let eased = self.curve.apply(breath.level) as u64;
+        let one = curve::ONE as u64;
+        let duty = self.min_duty + ((range * eased + one / 2) / one) as u32;
+        self.set_duty(duty);
+    }
+}
+
+// An RGB LED on three PWM outputs, breathing in a colour per phase that
+// crossfades whenever it changes
+pub struct RgbLed<R: PwmFader, G: PwmFader, B: PwmFader> {
+    pub red: R,
+    pub green: G,
+    pub blue: B,
+    // Brightness range the breath moves through, from 0 to `curve::ONE`
+    pub min: u32,
+    pub max: u32,
+    pub curve: Curve,
+    pub colours: PhaseColours,
+    pub airless_at_floor: bool,
+    // Colour shown right now, part way from `from` to `to`
+    colour: Rgb,
+    from: Rgb,
+    to: Option<Rgb>,
+    fade_start_ms: u64,
+    duties: [DutyCache; 3],
+}
+
+impl<R: PwmFader, G: PwmFader, B: PwmFader> RgbLed<R, G, B> {
+    pub fn new(red: R, green: G, blue: B) -> Self {
+        let white = Rgb::new(255, 255, 255);
+        RgbLed {
+            red,
+            green,
+            blue,
+            min: 0,
+            max: curve::ONE,
+            curve: Curve::Gamma,
+            colours: PhaseColours::new(),
+            airless_at_floor: true,
+            colour: white,
+            from: white,
+            to: None,
+            fade_start_ms: 0,
+            duties: Default::default(),
+        }
+    }
+
+    pub fn set_brightness_pct(&mut self, min_pct: u8, max_pct: u8) {
+        self.min = min_pct.min(100) as u32 * curve::ONE / 100;
+        self.max = max_pct.min(100) as u32 * curve::ONE / 100;
+    }
+
+    // Called every time round the loop with the colour wanted. A new colour
+    // is crossfaded to from whatever is showing, and the first is jumped to.
+    pub fn set_colour(&mut self, colour: Rgb, now_ms: u64) {
+        match self.to {
+            Some(to) if to == colour => {}
+            Some(_) => {
+                self.from = self.colour;
+                self.to = Some(colour);
+                self.fade_start_ms = now_ms;
+            }
+            None => {
+                self.from = colour;
+                self.to = Some(colour);
+            }
+        }
+
+        let elapsed = now_ms.saturating_sub(self.fade_start_ms);
+        let amount = if elapsed >= constants::COLOUR_CROSSFADE_MS {
+            curve::ONE
+        } else {
+            (elapsed * curve::ONE as u64 / constants::COLOUR_CROSSFADE_MS) as u32
+        };
+        self.colour = self.from.blend(colour, amount);
+    }
+
+    fn set_duties(&mut self, duties: [u32; 3]) {
+        self.duties[0].write(&mut self.red, duties[0]);
+        self.duties[1].write(&mut self.green, duties[1]);
+        self.duties[2].write(&mut self.blue, duties[2]);
+    }
+}
+
+impl<R: PwmFader, G: PwmFader, B: PwmFader> BreathIndicator for RgbLed<R, G, B> {
+    fn configure(&mut self, config: &Config) {
+        let (floor, max) = brightness_pct(config);
+        self.set_brightness_pct(floor, max);
+        self.curve = Curve::from_config(config);
+        self.colours = PhaseColours::from_config(config);
+        self.airless_at_floor = airless_at_floor(config);
+    }
+
+    fn render(&mut self, engine: &BreathingEngine, now_ms: u64) {
+        let breath = engine.breath(now_ms);
+        self.set_colour(self.colours.for_phase(breath.phase).rgb(), now_ms);
+        if breath.phase == Phase::Airless && !self.airless_at_floor {
+            self.set_duties([0, 0, 0]);
+            return;
+        }
+        let range = self.max.saturating_sub(self.min) as u64;
+        let one = curve::ONE as u64;
+        let eased = self.curve.apply(breath.level) as u64;
+        let brightness = self.min as u64 + range * eased / one;
+        // Each channel's share of its own full duty
+        let duty = |max_duty: u32, channel: u8| {
+            return (max_duty as u64 * brightness * channel as u64 / (one * 255)) as u32;
+        };
+        self.set_duties([
+            duty(self.red.max_duty(), self.colour.r),
+            duty(self.green.max_duty(), self.colour.g),
+            duty(self.blue.max_duty(), self.colour.b),
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breathing::Timings;
+    use crate::colour::Colour;
+    use crate::hal::fakes::FakeFader;
+
+    const RED: Rgb = Rgb::new(255, 0, 0);
+    const GREEN: Rgb = Rgb::new(0, 255, 0);
+    const BLUE: Rgb = Rgb::new(0, 0, 255);
+
+    // Channels with a full duty of 255, so duties read as channel levels
+    fn rgb_led() -> RgbLed<FakeFader, FakeFader, FakeFader> {
+        let mut led = RgbLed::new(
+            FakeFader::new(255),
+            FakeFader::new(255),
+            FakeFader::new(255),
+        );
+        led.curve = Curve::Linear;
+        return led;
+    }
+
+    // A full duty of 1000, so duties read as tenths of a percent
+    fn plain_led(floor_pct: u8) -> BreathingLed<FakeFader> {
+        let mut led = BreathingLed::new(FakeFader::new(1000));
+        led.curve = Curve::Linear;
+        led.set_brightness_pct(floor_pct, 100);
+        return led;
+    }
+
+    // The lowest and highest duties through the exhale and airless pause
+    fn exhale_duties(led: &mut BreathingLed<FakeFader>) -> (u32, u32) {
+        let mut engine = engine();
+        engine.poll(6000);
+        let (mut lowest, mut highest) = (u32::MAX, 0);
+        for now_ms in (6000..12000).step_by(100) {
+            engine.poll(now_ms);
+            led.render(&engine, now_ms);
+            lowest = lowest.min(led.fader.duty);
+            highest = highest.max(led.fader.duty);
+        }
+        return (lowest, highest);
+    }
+
+    fn duties(led: &RgbLed<FakeFader, FakeFader, FakeFader>) -> [u32; 3] {
+        return [led.red.duty, led.green.duty, led.blue.duty];
+    }
+
+    fn engine() -> BreathingEngine {
+        let mut engine = BreathingEngine::new(Timings {
+            inhale_ms: 4000,
+            top_up_ms: 0,
+            hold_ms: 2000,
+            exhale_ms: 4000,
+            airless_ms: 2000,
+        });
+        engine.poll(0);
+        return engine;
+    }
+
+    #[test]
+    fn the_first_colour_is_jumped_to() {
+        let mut led = rgb_led();
+        led.set_colour(RED, 1000);
+        assert_eq!(led.colour, RED);
+    }
+
+    #[test]
+    fn a_new_colour_crossfades() {
+        let mut led = rgb_led();
+        led.set_colour(RED, 0);
+        let cases = [
+            (1000, RED),
+            (1300, Rgb::new(128, 0, 127)),
+            (1600, BLUE),
+            (2000, BLUE),
+        ];
+        for (now_ms, colour) in cases {
+            led.set_colour(BLUE, now_ms);
+            assert_eq!(led.colour, colour, "at {}", now_ms);
+        }
+    }
+
+    #[test]
+    fn a_new_colour_mid_fade_fades_from_what_is_showing() {
+        let mut led = rgb_led();
+        led.set_colour(RED, 0);
+        led.set_colour(BLUE, 1000);
+        led.set_colour(BLUE, 1300);
+        let halfway = Rgb::new(128, 0, 127);
+        assert_eq!(led.colour, halfway);
+
+        led.set_colour(GREEN, 1300);
+        assert_eq!(led.colour, halfway);
+        led.set_colour(GREEN, 1600);
+        assert_eq!(led.colour, Rgb::new(64, 127, 64));
+        led.set_colour(GREEN, 1900);
+        assert_eq!(led.colour, GREEN);
+    }
+
+    #[test]
+    fn the_rgb_led_breathes_in_the_phases_colour() {
+        let mut engine = engine();
+        let mut led = rgb_led();
+        led.render(&engine, 2000);
+        assert_eq!(duties(&led), [0, 0, 127]);
+
+        // Fading from blue into the hold's white
+        engine.poll(4000);
+        led.render(&engine, 4000);
+        assert_eq!(duties(&led), [0, 0, 255]);
+        led.render(&engine, 4000 + constants::COLOUR_CROSSFADE_MS);
+        assert_eq!(duties(&led), [255, 255, 255]);
+    }
+
+    #[test]
+    fn the_airless_colour_needs_a_floor() {
+        let mut engine = engine();
+        let mut led = rgb_led();
+        led.colours.airless = Colour::Red;
+        engine.poll(10000);
+        led.render(&engine, 10000 + constants::COLOUR_CROSSFADE_MS);
+        assert_eq!(duties(&led), [0, 0, 0]);
+
+        led.set_brightness_pct(20, 100);
+        led.render(&engine, 11000);
+        assert_eq!(duties(&led), [50, 0, 0]);
+    }
+
+    #[test]
+    fn the_led_never_goes_below_the_floor() {
+        let mut led = plain_led(20);
+        assert_eq!(exhale_duties(&mut led), (200, 1000));
+        // Resting there through the airless pause
+        assert_eq!(led.fader.duty, 200);
+    }
+
+    #[test]
+    fn no_floor_breathes_all_the_way_out() {
+        let mut led = plain_led(0);
+        assert_eq!(exhale_duties(&mut led), (0, 1000));
+        assert_eq!(led.fader.duty, 0);
+    }
+
+    #[test]
+    fn airless_pauses_can_turn_the_led_off() {
+        let mut config = Config::new();
+        config.set(SettingName::MinBrightnessPct, 20).unwrap();
+        config.set(SettingName::AirlessAtFloor, 0).unwrap();
+        let mut led = plain_led(0);
+        led.configure(&config);
+        assert!(!led.airless_at_floor);
+        assert_eq!(led.min_duty, 200);
+
+        let mut engine = engine();
+        engine.poll(10000);
+        led.render(&engine, 10000);
+        assert_eq!(led.fader.duty, 0);
+        // Back to the floor for the next inhale
+        engine.poll(12000);
+        led.render(&engine, 12000);
+        assert_eq!(led.fader.duty, 200);
+    }
+
+    #[test]
+    fn an_unchanged_duty_isnt_written_again() {
+        let engine = engine();
+        let mut led = plain_led(0);
+        led.render(&engine, 2000);
+        led.render(&engine, 2000);
+        assert_eq!(led.fader.writes, 1);
+    }
+}
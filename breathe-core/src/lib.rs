@@ -0,0 +1,24 @@
+#![no_std]
+
+#[macro_use]
+mod listed;
+
+pub mod breathing;
+pub mod calibration;
+pub mod colour;
+pub mod config;
+pub mod constants;
+pub mod cue;
+pub mod curve;
+pub mod encoder;
+pub mod event;
+pub mod gesture;
+pub mod hal;
+pub mod haptic;
+pub mod indicator;
+pub mod led;
+pub mod pot;
+pub mod preset;
+pub mod shell;
+pub mod storage;
+pub mod strip;
@@ -0,0 +1,59 @@
+// Declares an enum picked from a fixed list, e.g. a setting's choices, along
+// with the list and the names each value is shown and typed as. Settings store
+// a value's index into the list.
+//
+//     listed_enum! {
+//         pub enum Shape in SHAPES {
+//             Round => ("Round", "round"),
+//             Square => ("Square", "square"),
+//         }
+//     }
+macro_rules! listed_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident in $list:ident {
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident => ($label:literal, $key:literal),
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(PartialEq, Copy, Clone, Debug)]
+        pub enum $name {
+            $(
+                $(#[$variant_meta])*
+                $variant,
+            )*
+        }
+
+        pub const $list: [$name; [$($key),*].len()] = [$($name::$variant),*];
+
+        impl $name {
+            pub fn from_index(index: u16) -> Option<$name> {
+                $list.get(index as usize).copied()
+            }
+
+            pub fn index(&self) -> u16 {
+                $list.iter().position(|value| value == self).unwrap() as u16
+            }
+
+            pub fn as_str<'a>(&self) -> &'a str {
+                match self {
+                    $($name::$variant => return $label,)*
+                }
+            }
+
+            // Short lowercase name, for typing on the serial console
+            pub fn key<'a>(&self) -> &'a str {
+                match self {
+                    $($name::$variant => return $key,)*
+                }
+            }
+
+            pub fn from_key(key: &str) -> Option<$name> {
+                $list.into_iter().find(|value| value.key() == key)
+            }
+        }
+    };
+}
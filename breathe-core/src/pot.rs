@@ -0,0 +1,253 @@
+use crate::calibration::PotCalibration;
+use crate::constants::POSITION_MAX;
+use crate::hal::AnalogInput;
+
+// Most samples taken for a single read
+pub const MAX_READ_COUNT: u16 = 16;
+
+// How the samples taken for one read are combined
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum SampleFilter {
+    Mean,
+    // Ignores the odd wild sample, which a mean gets dragged around by
+    Median,
+}
+
+// Readings go through each stage in turn: the samples for a read are combined,
+// smoothed against earlier reads, then held still with hysteresis
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct PotFilter {
+    pub samples: SampleFilter,
+    // Exponential moving average across reads, weighting the newest by
+    // 1 / 2^ema_shift. 0 turns it off.
+    pub ema_shift: u8,
+    // How far a reading must move, in raw ADC units, before the position
+    // changes
+    pub hysteresis: u16,
+}
+
+impl PotFilter {
+    pub fn none() -> Self {
+        PotFilter {
+            samples: SampleFilter::Mean,
+            ema_shift: 0,
+            hysteresis: 0,
+        }
+    }
+}
+
+// Maps raw readings from a potentiometer onto a position anywhere along its
+// travel
+pub struct Potentiometer<A: AnalogInput> {
+    pub input: A,
+    pub read_count: u16,
+    pub min_val: u16,
+    pub max_val: u16,
+    pub deadzone: u16,
+    pub filter: PotFilter,
+    // Running average, with 8 fractional bits
+    ema: Option<u32>,
+    // Last reading a position was taken from
+    held: Option<u16>,
+}
+
+impl<A: AnalogInput> Potentiometer<A> {
+    pub fn new(input: A) -> Self {
+        Potentiometer {
+            input,
+            read_count: 0,
+            min_val: 0,
+            max_val: 0,
+            deadzone: 0,
+            filter: PotFilter::none(),
+            ema: None,
+            held: None,
+        }
+    }
+
+    // Use the ends of travel measured for this unit
+    pub fn apply_calibration(&mut self, calibration: PotCalibration) {
+        self.min_val = calibration.min;
+        self.max_val = calibration.max;
+    }
+
+    // A filtered reading in raw ADC units, before it's mapped to a position
+    pub fn read_filtered(&mut self) -> u16 {
+        let sampled = self.sample();
+        return self.smooth(sampled);
+    }
+
+    // How far along its travel the pot is, from 0 to `POSITION_MAX`
+    pub fn read_position(&mut self) -> u16 {
+        let smoothed = self.read_filtered();
+
+        // Stay put unless the reading has moved by more than the hysteresis
+        let held = match self.held {
+            Some(held) if smoothed.abs_diff(held) <= self.filter.hysteresis => held,
+            _ => smoothed,
+        };
+        self.held = Some(held);
+
+        let (augmented_min, augmented_max) = self.bounds();
+        let offset = (held.clamp(augmented_min, augmented_max) - augmented_min) as u32;
+        let span = (augmented_max - augmented_min).max(1) as u32;
+        return (offset * POSITION_MAX as u32 / span) as u16;
+    }
+
+    // The calibrated range, widened by the deadzone so the ends are easy to hit
+    fn bounds(&self) -> (u16, u16) {
+        let augmented_min = self.min_val.saturating_sub(self.deadzone);
+        let augmented_max = self.max_val.saturating_add(self.deadzone);
+        return (augmented_min, augmented_max.max(augmented_min));
+    }
+
+    fn sample(&mut self) -> u16 {
+        let count = self.read_count.clamp(1, MAX_READ_COUNT) as usize;
+        let mut samples = [0u16; MAX_READ_COUNT as usize];
+        for sample in &mut samples[..count] {
+            *sample = self.input.read_raw();
+        }
+        let samples = &mut samples[..count];
+
+        match self.filter.samples {
+            SampleFilter::Mean => {
+                let sum: u32 = samples.iter().map(|v| *v as u32).sum();
+                return (sum / count as u32) as u16;
+            }
+            SampleFilter::Median => {
+                samples.sort_unstable();
+                return samples[count / 2];
+            }
+        }
+    }
+
+    fn smooth(&mut self, sampled: u16) -> u16 {
+        let sampled = (sampled as u32) << 8;
+        let ema = match self.ema {
+            Some(ema) if self.filter.ema_shift > 0 => {
+                // Adding and subtracting separately keeps this unsigned
+                ema - (ema >> self.filter.ema_shift) + (sampled >> self.filter.ema_shift)
+            }
+            _ => sampled,
+        };
+        self.ema = Some(ema);
+        return ((ema + 0x80) >> 8) as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SettingName;
+    use crate::constants::{POT_DEADZONE, POT_FILTER, POT_MAX, POT_MIN, POT_READ_COUNT};
+    use crate::hal::fakes::FakeAdc;
+
+    fn pot(readings: &[u16]) -> Potentiometer<FakeAdc<'_>> {
+        let mut pot = Potentiometer::new(FakeAdc::new(readings));
+        pot.read_count = 1;
+        pot.min_val = 1000;
+        pot.max_val = 3000;
+        return pot;
+    }
+
+    #[test]
+    fn ends_of_travel_map_to_the_ends_of_the_range() {
+        assert_eq!(pot(&[1000]).read_position(), 0);
+        assert_eq!(pot(&[3000]).read_position(), POSITION_MAX);
+        assert_eq!(pot(&[2000]).read_position(), POSITION_MAX / 2);
+        // Past either end still counts as the end
+        assert_eq!(pot(&[0]).read_position(), 0);
+        assert_eq!(pot(&[4095]).read_position(), POSITION_MAX);
+    }
+
+    #[test]
+    fn the_deadzone_widens_the_ends() {
+        let mut pot = pot(&[950]);
+        pot.deadzone = 100;
+        assert!(pot.read_position() > 0);
+        pot.input = FakeAdc::new(&[900]);
+        assert_eq!(pot.read_position(), 0);
+    }
+
+    #[test]
+    fn calibration_replaces_the_ends() {
+        let mut pot = pot(&[500]);
+        pot.apply_calibration(PotCalibration {
+            min: 500,
+            max: 1500,
+        });
+        assert_eq!(pot.read_position(), 0);
+        pot.input = FakeAdc::new(&[1500]);
+        assert_eq!(pot.read_position(), POSITION_MAX);
+    }
+
+    // A knob left alone near the middle of brightness's 50 to 55 pot step,
+    // as read from the board: a few counts of jitter and the odd wild sample
+    const NOISY: [u16; 20] = [
+        2003, 1998, 2011, 2006, 1995, 2009, 4095, 2001, 1997, 2012, 2004, 1999, 2008, 0, 1996,
+        2010, 2002, 2007, 1994, 2005,
+    ];
+
+    fn filtered_pot(readings: &[u16]) -> Potentiometer<FakeAdc<'_>> {
+        let mut pot = pot(readings);
+        pot.min_val = POT_MIN;
+        pot.max_val = POT_MAX;
+        pot.deadzone = POT_DEADZONE;
+        pot.read_count = POT_READ_COUNT;
+        pot.filter = POT_FILTER;
+        return pot;
+    }
+
+    #[test]
+    fn noisy_readings_settle() {
+        let mut pot = filtered_pot(&NOISY);
+        for _ in 0..10 {
+            pot.read_position();
+        }
+        let settled = pot.read_position();
+        for _ in 0..200 {
+            assert_eq!(pot.read_position(), settled);
+        }
+        // Near where the knob actually is, despite the wild samples
+        let raw = 2003 - (POT_MIN - POT_DEADZONE) as u32;
+        let span = (POT_MAX - POT_MIN + 2 * POT_DEADZONE) as u32;
+        let expected = (raw * POSITION_MAX as u32 / span) as u16;
+        assert!(settled.abs_diff(expected) < POSITION_MAX / 100);
+    }
+
+    #[test]
+    fn settings_dont_flicker_at_a_pot_step_boundary() {
+        let range = SettingName::BrightnessPct.range();
+        let mut pot = filtered_pot(&NOISY);
+        for _ in 0..10 {
+            pot.read_position();
+        }
+        let settled = range.value_at(pot.read_position());
+        for _ in 0..200 {
+            assert_eq!(range.value_at(pot.read_position()), settled);
+        }
+
+        // Whereas the raw readings land either side of it
+        let mut unfiltered = filtered_pot(&NOISY);
+        unfiltered.read_count = 1;
+        unfiltered.filter = PotFilter::none();
+        let mut values = [0u16; 20];
+        for value in &mut values {
+            *value = range.value_at(unfiltered.read_position());
+        }
+        assert!(values.contains(&50) && values.contains(&55));
+    }
+
+    #[test]
+    fn samples_are_combined_per_read() {
+        let readings = [1000, 1000, 3000];
+        let mut mean = pot(&readings);
+        mean.read_count = 3;
+        assert_eq!(mean.read_filtered(), 1666);
+
+        let mut median = pot(&readings);
+        median.read_count = 3;
+        median.filter.samples = SampleFilter::Median;
+        assert_eq!(median.read_filtered(), 1000);
+    }
+}
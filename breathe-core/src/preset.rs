@@ -0,0 +1,34 @@
+use crate::config::SettingName;
+
+listed_enum! {
+    // Named breathing techniques, each filling in every phase duration at once
+    pub enum Preset in PRESETS {
+        Box => ("Box", "box"),
+        FourSevenEight => ("4-7-8", "478"),
+        Coherent => ("Coherent", "coherent"),
+        PhysiologicalSigh => ("Physiological Sigh", "sigh"),
+    }
+}
+
+// The Preset setting's value when the phases aren't any preset's, e.g. after
+// fine-tuning one
+pub const CUSTOM: u16 = PRESETS.len() as u16;
+pub const CUSTOM_KEY: &str = "custom";
+
+impl Preset {
+    pub fn durations(&self) -> [(SettingName, u16); 5] {
+        let (inhale, top_up, hold, exhale, airless) = match self {
+            Preset::Box => (4000, 0, 4000, 4000, 4000),
+            Preset::FourSevenEight => (4000, 0, 7000, 8000, 0),
+            Preset::Coherent => (5500, 0, 0, 5500, 0),
+            Preset::PhysiologicalSigh => (3000, 1000, 0, 8000, 0),
+        };
+        [
+            (SettingName::InhaleTimeMs, inhale),
+            (SettingName::TopUpTimeMs, top_up),
+            (SettingName::HoldTimeMs, hold),
+            (SettingName::ExhaleTimeMs, exhale),
+            (SettingName::AirlessTimeMs, airless),
+        ]
+    }
+}
@@ -0,0 +1,412 @@
+use core::fmt::Write;
+
+use crate::colour::{Colour, COLOURS};
+use crate::config::{Config, PotMode, SettingName, POT_MODES};
+use crate::cue::{CueStyle, CUE_STYLES};
+use crate::curve::{Curve, CURVES};
+use crate::haptic::{HapticStyle, HAPTIC_STYLES};
+use crate::preset::{self, Preset, PRESETS};
+
+// Collects bytes from a serial port into lines
+pub struct LineBuffer<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    overflowed: bool,
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub fn new() -> Self {
+        LineBuffer {
+            buf: [0; N],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    // Returns the finished line once `byte` ends one. Lines too long for the
+    // buffer, or that aren't UTF-8, are dropped.
+    pub fn push(&mut self, byte: u8) -> Option<&str> {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = self.len;
+                let overflowed = self.overflowed;
+                self.len = 0;
+                self.overflowed = false;
+                if overflowed || len == 0 {
+                    return None;
+                }
+                return core::str::from_utf8(&self.buf[..len]).ok();
+            }
+            // Backspace and delete
+            0x08 | 0x7F => {
+                self.len = self.len.saturating_sub(1);
+            }
+            _ if self.len == N => self.overflowed = true,
+            _ => {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            }
+        }
+        return None;
+    }
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        LineBuffer::new()
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Command {
+    Get(Option<SettingName>),
+    Set(SettingName, u16),
+    List,
+    Preset(Preset),
+    Save,
+    Reset,
+    Help,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ParseError {
+    Empty,
+    UnknownCommand,
+    UnknownSetting,
+    UnknownPreset,
+    MissingArgument,
+    BadValue,
+    TooManyArguments,
+}
+
+impl ParseError {
+    pub fn as_str<'a>(&self) -> &'a str {
+        use ParseError::*;
+        match self {
+            Empty => return "Empty command",
+            UnknownCommand => return "Unknown command, try `help`",
+            UnknownSetting => return "Unknown setting, try `list`",
+            UnknownPreset => return "Unknown preset",
+            MissingArgument => return "Missing argument",
+            BadValue => return "Value must be a whole number, or a name from `list`",
+            TooManyArguments => return "Too many arguments",
+        }
+    }
+}
+
+pub fn parse(line: &str) -> Result<Command, ParseError> {
+    let mut words = line.split_whitespace();
+    let command = match words.next().ok_or(ParseError::Empty)? {
+        "get" => match words.next() {
+            Some(key) => Command::Get(Some(parse_setting(key)?)),
+            None => Command::Get(None),
+        },
+        "set" => {
+            let setting = parse_setting(words.next().ok_or(ParseError::MissingArgument)?)?;
+            let value = parse_value(setting, words.next().ok_or(ParseError::MissingArgument)?)?;
+            Command::Set(setting, value)
+        }
+        "list" => Command::List,
+        "preset" => {
+            let key = words.next().ok_or(ParseError::MissingArgument)?;
+            Command::Preset(Preset::from_key(key).ok_or(ParseError::UnknownPreset)?)
+        }
+        "save" => Command::Save,
+        "reset" => Command::Reset,
+        "help" => Command::Help,
+        _ => return Err(ParseError::UnknownCommand),
+    };
+
+    if words.next().is_some() {
+        return Err(ParseError::TooManyArguments);
+    }
+    return Ok(command);
+}
+
+fn parse_setting(key: &str) -> Result<SettingName, ParseError> {
+    SettingName::from_key(key).ok_or(ParseError::UnknownSetting)
+}
+
+// Settings that pick from a list can be given by name as well as by number
+fn parse_value(setting: SettingName, word: &str) -> Result<u16, ParseError> {
+    if let Ok(value) = word.parse::<u16>() {
+        return Ok(value);
+    }
+    let named = match setting {
+        SettingName::Preset if word == preset::CUSTOM_KEY => Some(preset::CUSTOM),
+        SettingName::Preset => Preset::from_key(word).map(|preset| preset.index()),
+        SettingName::Curve => Curve::from_key(word).map(|curve| curve.index()),
+        SettingName::CueStyle => CueStyle::from_key(word).map(|style| style.index()),
+        SettingName::HapticStyle => HapticStyle::from_key(word).map(|style| style.index()),
+        SettingName::PotMode => PotMode::from_key(word).map(|mode| mode.index()),
+        _ if is_colour(setting) => Colour::from_key(word).map(|colour| colour.index()),
+        _ => None,
+    };
+    return named.ok_or(ParseError::BadValue);
+}
+
+// What the caller has to do after a command, beyond what `execute` did itself
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Action {
+    None,
+    Save,
+}
+
+pub fn execute(
+    command: Command,
+    config: &mut Config,
+    out: &mut impl Write,
+) -> Result<Action, core::fmt::Error> {
+    match command {
+        Command::Get(Some(setting)) => write_setting(config, setting, out)?,
+        Command::Get(None) => {
+            for item in config.items {
+                write_setting(config, item.setting, out)?;
+            }
+        }
+        Command::Set(setting, value) => match config.set(setting, value) {
+            Ok(()) => write_setting(config, setting, out)?,
+            Err(e) => writeln!(out, "{}", e)?,
+        },
+        Command::List => {
+            for item in config.items {
+                let range = config.range_of(item.setting);
+                writeln!(
+                    out,
+                    "{:<12} {} ({} to {}, steps of {})",
+                    item.setting.key(),
+                    item.setting.as_str(),
+                    range.min,
+                    range.max,
+                    range.step
+                )?;
+            }
+            write!(out, "presets:")?;
+            for preset in PRESETS {
+                write!(out, " {}", preset.key())?;
+            }
+            writeln!(out)?;
+            write!(out, "curves:")?;
+            for curve in CURVES {
+                write!(out, " {}", curve.key())?;
+            }
+            writeln!(out)?;
+            write!(out, "colours:")?;
+            for colour in COLOURS {
+                write!(out, " {}", colour.key())?;
+            }
+            writeln!(out)?;
+            write!(out, "cues:")?;
+            for style in CUE_STYLES {
+                write!(out, " {}", style.key())?;
+            }
+            writeln!(out)?;
+            write!(out, "haptics:")?;
+            for style in HAPTIC_STYLES {
+                write!(out, " {}", style.key())?;
+            }
+            writeln!(out)?;
+            write!(out, "pot modes:")?;
+            for mode in POT_MODES {
+                write!(out, " {}", mode.key())?;
+            }
+            writeln!(out)?;
+        }
+        Command::Preset(preset) => {
+            config.apply_preset(preset);
+            writeln!(out, "Preset {} applied", preset.as_str())?;
+        }
+        Command::Save => return Ok(Action::Save),
+        Command::Reset => {
+            config.reset();
+            writeln!(out, "Settings reset to defaults")?;
+        }
+        Command::Help => {
+            writeln!(out, "get [setting]          show one or all settings")?;
+            writeln!(out, "set <setting> <value>  set an exact value")?;
+            writeln!(
+                out,
+                "list                   list settings, ranges and presets"
+            )?;
+            writeln!(out, "preset <name>          apply a preset")?;
+            writeln!(out, "save                   save settings to flash")?;
+            writeln!(out, "reset                  restore default settings")?;
+        }
+    }
+    return Ok(Action::None);
+}
+
+fn write_setting(
+    config: &Config,
+    setting: SettingName,
+    out: &mut impl Write,
+) -> Result<(), core::fmt::Error> {
+    let value = config.get(setting).unwrap_or(0);
+    match value_key(setting, value) {
+        Some(key) => writeln!(out, "{} = {}", setting.key(), key),
+        None => writeln!(out, "{} = {}", setting.key(), value),
+    }
+}
+
+// Name of a value from a list, for settings that pick from one
+fn value_key<'a>(setting: SettingName, value: u16) -> Option<&'a str> {
+    match setting {
+        SettingName::Preset if value == preset::CUSTOM => return Some(preset::CUSTOM_KEY),
+        SettingName::Preset => return Preset::from_index(value).map(|preset| preset.key()),
+        SettingName::Curve => return Curve::from_index(value).map(|curve| curve.key()),
+        SettingName::CueStyle => return CueStyle::from_index(value).map(|style| style.key()),
+        SettingName::HapticStyle => return HapticStyle::from_index(value).map(|style| style.key()),
+        SettingName::PotMode => return PotMode::from_index(value).map(|mode| mode.key()),
+        _ if is_colour(setting) => return Colour::from_index(value).map(|colour| colour.key()),
+        _ => return None,
+    }
+}
+
+fn is_colour(setting: SettingName) -> bool {
+    use SettingName::*;
+    return matches!(
+        setting,
+        InhaleColour | HoldColour | ExhaleColour | AirlessColour
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+
+    // Feeds a whole string in, returning the last line it finished
+    fn push_str<const N: usize>(buffer: &mut LineBuffer<N>, bytes: &[u8]) -> Option<String> {
+        let mut line = None;
+        for byte in bytes {
+            if let Some(finished) = buffer.push(*byte) {
+                line = Some(String::from(finished));
+            }
+        }
+        return line;
+    }
+
+    #[test]
+    fn lines_end_at_either_newline() {
+        let mut buffer = LineBuffer::<16>::new();
+        assert_eq!(push_str(&mut buffer, b"get\r"), Some(String::from("get")));
+        assert_eq!(push_str(&mut buffer, b"save\n"), Some(String::from("save")));
+        // The \n of a \r\n is an empty line, which is dropped
+        assert_eq!(
+            push_str(&mut buffer, b"list\r\n"),
+            Some(String::from("list"))
+        );
+        assert_eq!(push_str(&mut buffer, b"\n"), None);
+    }
+
+    #[test]
+    fn backspace_takes_back_a_byte() {
+        let mut buffer = LineBuffer::<16>::new();
+        assert_eq!(
+            push_str(&mut buffer, b"gex\x08t\r"),
+            Some(String::from("get"))
+        );
+        assert_eq!(
+            push_str(&mut buffer, b"\x7F\x7Fhelp\r"),
+            Some(String::from("help"))
+        );
+    }
+
+    #[test]
+    fn overflowing_lines_are_dropped() {
+        let mut buffer = LineBuffer::<4>::new();
+        assert_eq!(push_str(&mut buffer, b"list\r"), Some(String::from("list")));
+        assert_eq!(push_str(&mut buffer, b"lists\r"), None);
+        // Backspacing doesn't rescue it, and the next line starts afresh
+        assert_eq!(push_str(&mut buffer, b"lists\x08\r"), None);
+        assert_eq!(push_str(&mut buffer, b"save\r"), Some(String::from("save")));
+    }
+
+    #[test]
+    fn parses_each_command() {
+        assert_eq!(parse("get"), Ok(Command::Get(None)));
+        assert_eq!(
+            parse("get inhale"),
+            Ok(Command::Get(Some(SettingName::InhaleTimeMs)))
+        );
+        assert_eq!(
+            parse("set exhale 4500"),
+            Ok(Command::Set(SettingName::ExhaleTimeMs, 4500))
+        );
+        assert_eq!(parse("list"), Ok(Command::List));
+        assert_eq!(parse("preset box"), Ok(Command::Preset(Preset::Box)));
+        assert_eq!(parse("save"), Ok(Command::Save));
+        assert_eq!(parse("reset"), Ok(Command::Reset));
+        assert_eq!(parse("  help  "), Ok(Command::Help));
+    }
+
+    #[test]
+    fn values_can_be_given_by_name() {
+        assert_eq!(
+            parse("set curve linear"),
+            Ok(Command::Set(SettingName::Curve, Curve::Linear.index()))
+        );
+        assert_eq!(
+            parse("set preset custom"),
+            Ok(Command::Set(SettingName::Preset, preset::CUSTOM))
+        );
+        assert_eq!(
+            parse("set holdcolour red"),
+            Ok(Command::Set(SettingName::HoldColour, Colour::Red.index()))
+        );
+        assert_eq!(
+            parse("set haptics ramp"),
+            Ok(Command::Set(
+                SettingName::HapticStyle,
+                HapticStyle::Ramp.index()
+            ))
+        );
+        assert_eq!(
+            parse("set potmode relative"),
+            Ok(Command::Set(
+                SettingName::PotMode,
+                PotMode::Relative.index()
+            ))
+        );
+    }
+
+    #[test]
+    fn reports_each_parse_error() {
+        assert_eq!(parse(""), Err(ParseError::Empty));
+        assert_eq!(parse("   "), Err(ParseError::Empty));
+        assert_eq!(parse("fly"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("get nope"), Err(ParseError::UnknownSetting));
+        assert_eq!(parse("preset nope"), Err(ParseError::UnknownPreset));
+        assert_eq!(parse("set"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("set inhale"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("preset"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("set inhale slow"), Err(ParseError::BadValue));
+        // Names only count for settings that pick from a list
+        assert_eq!(parse("set brightness red"), Err(ParseError::BadValue));
+        assert_eq!(parse("save now"), Err(ParseError::TooManyArguments));
+        assert_eq!(
+            parse("set inhale 4000 5000"),
+            Err(ParseError::TooManyArguments)
+        );
+    }
+
+    #[test]
+    fn set_reports_the_new_value_or_why_not() {
+        let mut config = Config::new();
+        let mut out = String::new();
+        let command = parse("set curve linear").unwrap();
+        assert_eq!(execute(command, &mut config, &mut out), Ok(Action::None));
+        assert_eq!(out, "curve = linear\n");
+
+        out.clear();
+        let command = parse("set inhale 50").unwrap();
+        execute(command, &mut config, &mut out).unwrap();
+        assert_eq!(out, "Must be at least 3000\n");
+
+        let command = parse("save").unwrap();
+        assert_eq!(execute(command, &mut config, &mut out), Ok(Action::Save));
+    }
+}
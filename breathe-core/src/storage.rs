@@ -0,0 +1,440 @@
+use crate::calibration::PotCalibration;
+use crate::config::{Config, SettingName};
+
+// Config records are written one after another into fixed-size slots, wrapping
+// around the whole partition so every sector wears evenly. The newest valid
+// record wins on load; torn or corrupt ones fail their CRC and are skipped.
+pub const SECTOR_SIZE: u32 = 4096;
+pub const SLOT_SIZE: u32 = 128;
+const SLOTS_PER_SECTOR: u32 = SECTOR_SIZE / SLOT_SIZE;
+
+const MAGIC: u8 = 0xB5;
+const VERSION: u8 = 1;
+// Magic, version, sequence number and item count
+const HEADER_LEN: usize = 7;
+const ITEM_LEN: usize = 3;
+const CRC_LEN: usize = 4;
+
+// Per-device data shares the item format with settings, using ids well clear
+// of any SettingName so older firmware just skips them
+const POT_MIN_ID: u8 = 0x80;
+const POT_MAX_ID: u8 = 0x81;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum StorageError {
+    OutOfBounds,
+    Flash,
+    Verify,
+}
+
+// Raw NOR flash, addressed from the start of the reserved partition. Erasing
+// sets a whole sector to 0xFF, and writes can only clear bits.
+pub trait Flash {
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), StorageError>;
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), StorageError>;
+    fn erase_sector(&mut self, offset: u32) -> Result<(), StorageError>;
+}
+
+pub struct ConfigStore<F: Flash> {
+    flash: F,
+    slot_count: u32,
+    next_slot: u32,
+    seq: u32,
+    calibration: Option<PotCalibration>,
+    saved: Option<[u8; SLOT_SIZE as usize]>,
+    pending: Option<([u8; SLOT_SIZE as usize], u64)>,
+}
+
+impl<F: Flash> ConfigStore<F> {
+    pub fn new(flash: F, size: u32) -> Self {
+        ConfigStore {
+            flash,
+            slot_count: (size / SECTOR_SIZE) * SLOTS_PER_SECTOR,
+            next_slot: 0,
+            seq: 0,
+            calibration: None,
+            saved: None,
+            pending: None,
+        }
+    }
+
+    // Find the newest valid record, or None if there isn't one
+    pub fn load(&mut self) -> Option<Config> {
+        let mut newest: Option<(u32, u32)> = None;
+        let mut slot = [0u8; SLOT_SIZE as usize];
+        for index in 0..self.slot_count {
+            if self.flash.read(index * SLOT_SIZE, &mut slot).is_err() {
+                continue;
+            }
+            if let Some(seq) = check_record(&slot) {
+                let newer = match newest {
+                    Some((_, newest_seq)) => seq > newest_seq,
+                    None => true,
+                };
+                if newer {
+                    newest = Some((index, seq));
+                }
+            }
+        }
+
+        let (index, seq) = newest?;
+        self.flash.read(index * SLOT_SIZE, &mut slot).ok()?;
+        self.next_slot = (index + 1) % self.slot_count;
+        self.seq = seq;
+        self.saved = Some(payload(&slot));
+        let (config, calibration) = decode(&slot);
+        self.calibration = calibration;
+        return Some(config);
+    }
+
+    // Pot calibration from the last load, saved along with every config
+    pub fn calibration(&self) -> Option<PotCalibration> {
+        self.calibration
+    }
+
+    // Takes effect on the next save
+    pub fn set_calibration(&mut self, calibration: PotCalibration) {
+        self.calibration = Some(calibration);
+    }
+
+    pub fn save(&mut self, config: &Config) -> Result<(), StorageError> {
+        let mut result = Err(StorageError::OutOfBounds);
+        for _ in 0..self.slot_count {
+            let slot = self.next_slot;
+            self.next_slot = (self.next_slot + 1) % self.slot_count;
+            result = self.write_slot(slot, config);
+            if result.is_ok() {
+                break;
+            }
+        }
+        return result;
+    }
+
+    // Save once the config has stopped changing for `delay_ms`, so sweeping the
+    // pot doesn't write a record for every step
+    pub fn save_when_settled(
+        &mut self,
+        config: &Config,
+        now_ms: u64,
+        delay_ms: u64,
+    ) -> Option<Result<(), StorageError>> {
+        let current = payload(&encode(config, self.calibration, 0));
+        if self.saved == Some(current) {
+            self.pending = None;
+            return None;
+        }
+
+        match self.pending {
+            Some((pending, since)) if pending == current => {
+                if now_ms.saturating_sub(since) < delay_ms {
+                    return None;
+                }
+            }
+            _ => {
+                self.pending = Some((current, now_ms));
+                return None;
+            }
+        }
+
+        self.pending = None;
+        return Some(self.save(config));
+    }
+
+    fn write_slot(&mut self, slot: u32, config: &Config) -> Result<(), StorageError> {
+        let offset = slot * SLOT_SIZE;
+        // Moving onto a new sector, which only holds older records by now
+        if offset.is_multiple_of(SECTOR_SIZE) {
+            self.flash.erase_sector(offset)?;
+        }
+
+        let record = encode(config, self.calibration, self.seq.wrapping_add(1));
+        self.flash.write(offset, &record)?;
+
+        let mut written = [0u8; SLOT_SIZE as usize];
+        self.flash.read(offset, &mut written)?;
+        if written != record {
+            return Err(StorageError::Verify);
+        }
+
+        self.seq = self.seq.wrapping_add(1);
+        self.saved = Some(payload(&record));
+        return Ok(());
+    }
+}
+
+fn encode(
+    config: &Config,
+    calibration: Option<PotCalibration>,
+    seq: u32,
+) -> [u8; SLOT_SIZE as usize] {
+    let mut record = [0xFFu8; SLOT_SIZE as usize];
+    record[0] = MAGIC;
+    record[1] = VERSION;
+    record[2..6].copy_from_slice(&seq.to_le_bytes());
+
+    let mut pos = HEADER_LEN;
+    let mut count = 0;
+    let mut push_item = |id: u8, value: u16| {
+        record[pos] = id;
+        record[pos + 1..pos + ITEM_LEN].copy_from_slice(&value.to_le_bytes());
+        pos += ITEM_LEN;
+        count += 1;
+    };
+    for item in config.items {
+        push_item(item.setting.id(), item.value);
+    }
+    if let Some(calibration) = calibration {
+        push_item(POT_MIN_ID, calibration.min);
+        push_item(POT_MAX_ID, calibration.max);
+    }
+    record[6] = count;
+
+    let crc = crc32(&record[..pos]);
+    record[pos..pos + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+    return record;
+}
+
+// Returns the record's sequence number if it's intact and in this version's format
+fn check_record(record: &[u8; SLOT_SIZE as usize]) -> Option<u32> {
+    if record[0] != MAGIC || record[1] != VERSION {
+        return None;
+    }
+    let end = HEADER_LEN + record[6] as usize * ITEM_LEN;
+    if end + CRC_LEN > record.len() {
+        return None;
+    }
+    let crc = u32::from_le_bytes(record[end..end + CRC_LEN].try_into().unwrap());
+    if crc != crc32(&record[..end]) {
+        return None;
+    }
+    return Some(u32::from_le_bytes(record[2..6].try_into().unwrap()));
+}
+
+// Settings missing from the record keep their defaults, unknown ones are dropped
+fn decode(record: &[u8; SLOT_SIZE as usize]) -> (Config, Option<PotCalibration>) {
+    let mut config = Config::new();
+    let mut pot_min = None;
+    let mut pot_max = None;
+    for i in 0..record[6] as usize {
+        let pos = HEADER_LEN + i * ITEM_LEN;
+        let value = u16::from_le_bytes([record[pos + 1], record[pos + 2]]);
+        match record[pos] {
+            POT_MIN_ID => pot_min = Some(value),
+            POT_MAX_ID => pot_max = Some(value),
+            id => {
+                if let Some(setting) = SettingName::from_id(id) {
+                    // Out of range values keep their defaults too
+                    let _ = config.load_value(setting, value);
+                }
+            }
+        }
+    }
+    // Settings are loaded one at a time, so the floor is only checked against
+    // the brightness once both are in. One at or above it comes down to just
+    // below it.
+    let floor = config.get(SettingName::MinBrightnessPct).unwrap_or(0);
+    let floor = config.range_of(SettingName::MinBrightnessPct).snap(floor);
+    let _ = config.load_value(SettingName::MinBrightnessPct, floor);
+
+    let calibration = match (pot_min, pot_max) {
+        (Some(min), Some(max)) if min < max => Some(PotCalibration { min, max }),
+        _ => None,
+    };
+    return (config, calibration);
+}
+
+// Record contents without the sequence number, to tell whether a save is needed
+fn payload(record: &[u8; SLOT_SIZE as usize]) -> [u8; SLOT_SIZE as usize] {
+    let mut payload = *record;
+    payload[2..6].fill(0);
+    let end = HEADER_LEN + payload[6] as usize * ITEM_LEN;
+    payload[end..end + CRC_LEN].fill(0);
+    return payload;
+}
+
+// CRC-32 (IEEE 802.3), bit by bit since records are tiny
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    return !crc;
+}
+
+// Flash held in RAM with the same erase and write rules as the real thing
+pub struct MemFlash<const N: usize> {
+    pub data: [u8; N],
+}
+
+impl<const N: usize> MemFlash<N> {
+    pub fn new() -> Self {
+        MemFlash { data: [0xFF; N] }
+    }
+}
+
+impl<const N: usize> Default for MemFlash<N> {
+    fn default() -> Self {
+        MemFlash::new()
+    }
+}
+
+impl<const N: usize> MemFlash<N> {
+    fn range(&self, offset: u32, len: usize) -> Result<core::ops::Range<usize>, StorageError> {
+        let start = offset as usize;
+        if start + len > N {
+            return Err(StorageError::OutOfBounds);
+        }
+        return Ok(start..start + len);
+    }
+}
+
+impl<const N: usize> Flash for MemFlash<N> {
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), StorageError> {
+        let range = self.range(offset, buf.len())?;
+        buf.copy_from_slice(&self.data[range]);
+        return Ok(());
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), StorageError> {
+        let range = self.range(offset, data.len())?;
+        for (cell, byte) in self.data[range].iter_mut().zip(data) {
+            *cell &= *byte;
+        }
+        return Ok(());
+    }
+
+    fn erase_sector(&mut self, offset: u32) -> Result<(), StorageError> {
+        let start = offset - offset % SECTOR_SIZE;
+        let range = self.range(start, SECTOR_SIZE as usize)?;
+        self.data[range].fill(0xFF);
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 2 * SECTOR_SIZE as usize;
+
+    fn store() -> ConfigStore<MemFlash<SIZE>> {
+        ConfigStore::new(MemFlash::new(), SIZE as u32)
+    }
+
+    // Reopen the same flash, as after a reboot
+    fn reopen(store: ConfigStore<MemFlash<SIZE>>) -> ConfigStore<MemFlash<SIZE>> {
+        ConfigStore::new(store.flash, SIZE as u32)
+    }
+
+    fn config_with_inhale(inhale_ms: u16) -> Config {
+        let mut config = Config::new();
+        config.set(SettingName::InhaleTimeMs, inhale_ms).unwrap();
+        config
+    }
+
+    fn inhale(config: &Config) -> Option<u16> {
+        config.get(SettingName::InhaleTimeMs)
+    }
+
+    #[test]
+    fn erased_flash_has_nothing_to_load() {
+        assert!(store().load().is_none());
+    }
+
+    #[test]
+    fn saves_load_back() {
+        let mut store = store();
+        let mut config = config_with_inhale(4500);
+        config.set(SettingName::BrightnessPct, 60).unwrap();
+        store.set_calibration(PotCalibration {
+            min: 400,
+            max: 3500,
+        });
+        store.save(&config).unwrap();
+
+        let mut store = reopen(store);
+        let loaded = store.load().unwrap();
+        for item in config.items {
+            assert_eq!(loaded.get(item.setting), Some(item.value));
+        }
+        assert_eq!(
+            store.calibration(),
+            Some(PotCalibration {
+                min: 400,
+                max: 3500
+            })
+        );
+    }
+
+    #[test]
+    fn newest_record_wins_after_wrapping_around() {
+        let mut store = store();
+        let slots = SIZE as u32 / SLOT_SIZE;
+        // Round the partition and a few slots into the first sector again
+        for save in 0..slots + 5 {
+            store
+                .save(&config_with_inhale(3000 + (save as u16 % 70) * 100))
+                .unwrap();
+        }
+        let last = 3000 + ((slots + 4) as u16 % 70) * 100;
+
+        let mut store = reopen(store);
+        assert_eq!(inhale(&store.load().unwrap()), Some(last));
+
+        // And carries on after it
+        store.save(&config_with_inhale(9900)).unwrap();
+        let mut store = reopen(store);
+        assert_eq!(inhale(&store.load().unwrap()), Some(9900));
+    }
+
+    #[test]
+    fn corrupt_records_fall_back_to_the_one_before() {
+        let mut store = store();
+        store.save(&config_with_inhale(4000)).unwrap();
+        store.save(&config_with_inhale(5000)).unwrap();
+
+        // One flipped bit fails the CRC
+        let mut flipped = reopen(store);
+        flipped.flash.data[SLOT_SIZE as usize + HEADER_LEN + 1] ^= 0x01;
+        assert_eq!(inhale(&flipped.load().unwrap()), Some(4000));
+
+        // As does a write that was cut off part way, leaving the rest erased
+        let mut store = ConfigStore::new(MemFlash::<SIZE>::new(), SIZE as u32);
+        store.save(&config_with_inhale(4000)).unwrap();
+        store.save(&config_with_inhale(5000)).unwrap();
+        let mut torn = reopen(store);
+        let slot = SLOT_SIZE as usize;
+        torn.flash.data[slot + 20..2 * slot].fill(0xFF);
+        assert_eq!(inhale(&torn.load().unwrap()), Some(4000));
+    }
+
+    #[test]
+    fn a_saved_floor_above_the_brightness_comes_down() {
+        let mut store = store();
+        let mut config = Config::new();
+        config.load_value(SettingName::BrightnessPct, 30).unwrap();
+        config
+            .load_value(SettingName::MinBrightnessPct, 50)
+            .unwrap();
+        store.save(&config).unwrap();
+
+        let loaded = reopen(store).load().unwrap();
+        assert_eq!(loaded.get(SettingName::BrightnessPct), Some(30));
+        assert_eq!(loaded.get(SettingName::MinBrightnessPct), Some(29));
+    }
+
+    #[test]
+    fn settled_saves_wait_for_changes_to_stop() {
+        let mut store = store();
+        let config = config_with_inhale(4000);
+        assert_eq!(store.save_when_settled(&config, 0, 5000), None);
+        assert_eq!(store.save_when_settled(&config, 4999, 5000), None);
+        assert_eq!(store.save_when_settled(&config, 5000, 5000), Some(Ok(())));
+        // Nothing new to save
+        assert_eq!(store.save_when_settled(&config, 20000, 5000), None);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::breathing::{BreathingEngine, Phase};
+use crate::colour::{PhaseColours, Rgb};
+use crate::config::{Config, SettingName};
+use crate::curve::{self, Curve};
+use crate::indicator::BreathIndicator;
+
+// What an addressable strip shows through the breath
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Animation {
+    // Fills up with the inhale and empties with the exhale. Holds and airless
+    // pauses count down, emptying as the time runs out.
+    Bar,
+    // Every pixel breathes together, e.g. for a ring
+    Ring,
+}
+
+// Draws frames of N pixels from the breathing engine, for a strip to show.
+// Pixels are in the strip's order, starting from the end nearest the data
+// pin.
+pub struct StripRenderer<const N: usize> {
+    pub animation: Animation,
+    pub curve: Curve,
+    // Brightness of a fully lit pixel, 0 to 100
+    pub brightness_pct: u8,
+    pub colours: PhaseColours,
+    frame: [Rgb; N],
+    // Whether the frame changed since `take_changed` was last called
+    changed: bool,
+}
+
+impl<const N: usize> StripRenderer<N> {
+    pub fn new(animation: Animation) -> Self {
+        StripRenderer {
+            animation,
+            curve: Curve::Gamma,
+            brightness_pct: 100,
+            colours: PhaseColours::new(),
+            frame: [Rgb::new(0, 0, 0); N],
+            changed: false,
+        }
+    }
+
+    pub fn frame(&self) -> &[Rgb; N] {
+        &self.frame
+    }
+
+    // Whether the frame changed and needs sending to the strip, clearing it
+    pub fn take_changed(&mut self) -> bool {
+        let changed = self.changed;
+        self.changed = false;
+        return changed;
+    }
+}
+
+impl<const N: usize> BreathIndicator for StripRenderer<N> {
+    // Pick up the curve, brightness and colours from config
+    fn configure(&mut self, config: &Config) {
+        self.curve = Curve::from_config(config);
+        self.brightness_pct = config.get(SettingName::BrightnessPct).unwrap_or(100) as u8;
+        self.colours = PhaseColours::from_config(config);
+    }
+
+    // Draw the breath as it is at `now_ms` in its phase's colour
+    fn render(&mut self, engine: &BreathingEngine, now_ms: u64) {
+        let previous = self.frame;
+        let colour = self.colours.for_phase(engine.phase()).rgb();
+        match self.animation {
+            Animation::Bar => {
+                let fill = match engine.phase() {
+                    Phase::Hold | Phase::Airless => curve::ONE - engine.progress(now_ms),
+                    _ => engine.level(now_ms),
+                };
+                // How many pixels are lit, the last one partly
+                let lit = fill as u64 * N as u64;
+                for (index, pixel) in self.frame.iter_mut().enumerate() {
+                    let start = index as u64 * curve::ONE as u64;
+                    let amount = lit.saturating_sub(start).min(curve::ONE as u64) as u32;
+                    *pixel = scale(colour, self.curve.apply(amount), self.brightness_pct);
+                }
+            }
+            Animation::Ring => {
+                let amount = self.curve.apply(engine.level(now_ms));
+                let pixel = scale(colour, amount, self.brightness_pct);
+                self.frame = [pixel; N];
+            }
+        }
+        self.changed |= self.frame != previous;
+    }
+}
+
+// A colour at some brightness, with `amount` from 0 to `curve::ONE`
+fn scale(colour: Rgb, amount: u32, brightness_pct: u8) -> Rgb {
+    let factor = amount.min(curve::ONE) as u64 * brightness_pct.min(100) as u64;
+    let channel = |value: u8| (value as u64 * factor / (curve::ONE as u64 * 100)) as u8;
+    return Rgb::new(channel(colour.r), channel(colour.g), channel(colour.b));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breathing::Timings;
+    use crate::colour::Colour;
+
+    const OFF: Rgb = Rgb::new(0, 0, 0);
+    const FULL: Rgb = Rgb::new(255, 255, 255);
+
+    // White in every phase on a straight line, so pixels read as fill levels
+    fn renderer(animation: Animation) -> StripRenderer<4> {
+        let mut renderer = StripRenderer::new(animation);
+        renderer.curve = Curve::Linear;
+        renderer.colours = PhaseColours {
+            inhale: Colour::White,
+            hold: Colour::White,
+            exhale: Colour::White,
+            airless: Colour::White,
+        };
+        return renderer;
+    }
+
+    fn engine() -> BreathingEngine {
+        let mut engine = BreathingEngine::new(Timings {
+            inhale_ms: 4000,
+            top_up_ms: 0,
+            hold_ms: 2000,
+            exhale_ms: 4000,
+            airless_ms: 0,
+        });
+        engine.poll(0);
+        return engine;
+    }
+
+    #[test]
+    fn the_bar_fills_with_the_inhale() {
+        let engine = engine();
+        let mut renderer = renderer(Animation::Bar);
+        renderer.render(&engine, 0);
+        assert_eq!(renderer.frame(), &[OFF; 4]);
+        renderer.render(&engine, 4000);
+        assert_eq!(renderer.frame(), &[FULL; 4]);
+    }
+
+    #[test]
+    fn the_bars_last_pixel_is_partly_lit() {
+        let engine = engine();
+        let mut renderer = renderer(Animation::Bar);
+        // 1.5 pixels' worth
+        renderer.render(&engine, 1500);
+        assert_eq!(renderer.frame(), &[FULL, Rgb::new(127, 127, 127), OFF, OFF]);
+    }
+
+    #[test]
+    fn the_bar_counts_down_through_a_hold() {
+        let mut engine = engine();
+        let mut renderer = renderer(Animation::Bar);
+        assert_eq!(engine.poll(4000), Some(Phase::Hold));
+        renderer.render(&engine, 4000);
+        assert_eq!(renderer.frame(), &[FULL; 4]);
+        renderer.render(&engine, 4500);
+        assert_eq!(renderer.frame(), &[FULL, FULL, FULL, OFF]);
+        renderer.render(&engine, 5000);
+        assert_eq!(renderer.frame(), &[FULL, FULL, OFF, OFF]);
+    }
+
+    #[test]
+    fn the_ring_breathes_as_one() {
+        let engine = engine();
+        let mut renderer = renderer(Animation::Ring);
+        renderer.brightness_pct = 50;
+        renderer.render(&engine, 2000);
+        assert!(renderer.take_changed());
+        assert_eq!(renderer.frame(), &[Rgb::new(63, 63, 63); 4]);
+        // Nothing to send when nothing changed
+        renderer.render(&engine, 2000);
+        assert!(!renderer.take_changed());
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::Instant;
+
+use breathe_core::hal::{AnalogInput, Clock, PwmFader};
+
+pub struct SimClock {
+    start: Instant,
+}
+
+impl SimClock {
+    pub fn new() -> Self {
+        SimClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SimClock {
+    fn now_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+}
+
+// Knob position as a percentage, read back like the 12-bit ADC on the device
+pub struct SimPot {
+    pub position_pct: u8,
+}
+
+impl SimPot {
+    pub fn turn(&mut self, delta_pct: i8) {
+        self.position_pct = (self.position_pct as i16 + delta_pct as i16).clamp(0, 100) as u8;
+    }
+}
+
+impl AnalogInput for SimPot {
+    fn read_raw(&mut self) -> u16 {
+        self.position_pct as u16 * 4095 / 100
+    }
+}
+
+// Holds the duty it was last set to, so it can be drawn. 16-bit, like the
+// finest the LEDC timers go.
+pub struct SimLed {
+    duty: u32,
+}
+
+const SIM_LED_MAX_DUTY: u32 = u16::MAX as u32;
+
+impl SimLed {
+    pub fn new() -> Self {
+        SimLed { duty: 0 }
+    }
+
+    // Fraction of full brightness, 0 to 1
+    pub fn brightness(&self) -> f32 {
+        self.duty as f32 / SIM_LED_MAX_DUTY as f32
+    }
+}
+
+impl PwmFader for SimLed {
+    fn max_duty(&self) -> u32 {
+        SIM_LED_MAX_DUTY
+    }
+
+    fn set_duty(&mut self, duty: u32) {
+        self.duty = duty.min(SIM_LED_MAX_DUTY);
+    }
+}
@@ -0,0 +1,215 @@
+// Runs the breathing device on a terminal: left/right arrows turn the pot,
+// space clicks the Mode Selector button, p pauses like a double click, s
+// switches the strip's animation and q quits.
+mod board;
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use breathe_core::{
+    breathing, colour::Rgb, config, constants, hal::Clock, indicator::BreathIndicator, led::RgbLed,
+    pot, strip,
+};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    style::{Color, ResetColor, SetForegroundColor},
+    terminal,
+};
+
+const POT_STEP_PCT: i8 = 5;
+const BAR_WIDTH: usize = 40;
+const STRIP_LEN: usize = 12;
+const FRAME_MS: u64 = 20;
+
+// What's shown after the bar
+struct Status<'a> {
+    phase: &'a str,
+    remaining_ms: u16,
+    current: config::ConfigItem,
+    knob_pct: u8,
+    picked_up: bool,
+}
+
+enum Input {
+    Turn(i8),
+    Press,
+    Pause,
+    SwitchAnimation,
+    Quit,
+}
+
+fn main() -> io::Result<()> {
+    terminal::enable_raw_mode()?;
+    let result = run();
+    terminal::disable_raw_mode()?;
+    println!();
+    return result;
+}
+
+fn run() -> io::Result<()> {
+    let clock = board::SimClock::new();
+    let mut pot = pot::Potentiometer::new(board::SimPot { position_pct: 0 });
+    // Same pot calibration as the device, so positions line up with the real knob
+    pot.min_val = constants::POT_MIN;
+    pot.max_val = constants::POT_MAX;
+    pot.deadzone = constants::POT_DEADZONE;
+    pot.read_count = constants::POT_READ_COUNT;
+    pot.filter = constants::POT_FILTER;
+
+    let mut conf = config::Config::new();
+    // No buzzer or vibration motor to try these out on
+    conf.hide(config::SettingName::VolumePct);
+    conf.hide(config::SettingName::CueStyle);
+    conf.hide(config::SettingName::HapticStyle);
+    let mut engine = breathing::BreathingEngine::new(breathing::Timings::from_config(&conf));
+    // An RGB LED, so phase colours can be tried out
+    let mut breathing_led = RgbLed::new(
+        board::SimLed::new(),
+        board::SimLed::new(),
+        board::SimLed::new(),
+    );
+
+    let mut strip_renderer = strip::StripRenderer::<STRIP_LEN>::new(strip::Animation::Bar);
+
+    let mut last_position = pot.read_position();
+
+    let mut stdout = io::stdout();
+    write!(stdout, "{}", cursor::Hide)?;
+
+    loop {
+        match read_input()? {
+            Some(Input::Turn(delta)) => pot.input.turn(delta),
+            Some(Input::Press) => conf.next_item(),
+            Some(Input::Pause) => {
+                let now = clock.now_ms();
+                if engine.is_paused() {
+                    engine.resume(now);
+                } else {
+                    engine.pause(now);
+                }
+            }
+            Some(Input::SwitchAnimation) => {
+                strip_renderer.animation = match strip_renderer.animation {
+                    strip::Animation::Bar => strip::Animation::Ring,
+                    strip::Animation::Ring => strip::Animation::Bar,
+                };
+            }
+            Some(Input::Quit) => break,
+            None => {}
+        }
+
+        let now = clock.now_ms();
+
+        let position = pot.read_position();
+        if last_position != position {
+            conf.adjust_current_setting(position);
+        }
+        last_position = position;
+
+        breathing_led.configure(&conf);
+        strip_renderer.configure(&conf);
+        engine.set_timings(breathing::Timings::from_config(&conf));
+        engine.drive(now, &mut [&mut breathing_led, &mut strip_renderer]);
+
+        draw(
+            &mut stdout,
+            strip_renderer.frame(),
+            [
+                breathing_led.red.brightness(),
+                breathing_led.green.brightness(),
+                breathing_led.blue.brightness(),
+            ],
+            Status {
+                phase: if engine.is_paused() {
+                    "Paused"
+                } else {
+                    engine.phase().as_str()
+                },
+                remaining_ms: engine.remaining_ms(now),
+                current: conf.current_item(),
+                knob_pct: pot.input.position_pct,
+                picked_up: conf.is_picked_up(),
+            },
+        )?;
+    }
+
+    write!(stdout, "{}", cursor::Show)?;
+    return Ok(());
+}
+
+// Wait up to one frame for a key press
+fn read_input() -> io::Result<Option<Input>> {
+    if !event::poll(Duration::from_millis(FRAME_MS))? {
+        return Ok(None);
+    }
+    let key = match event::read()? {
+        Event::Key(key) if key.kind != KeyEventKind::Release => key,
+        _ => return Ok(None),
+    };
+    let input = match key.code {
+        KeyCode::Left | KeyCode::Down => Input::Turn(-POT_STEP_PCT),
+        KeyCode::Right | KeyCode::Up => Input::Turn(POT_STEP_PCT),
+        KeyCode::Char(' ') => Input::Press,
+        KeyCode::Char('p') => Input::Pause,
+        KeyCode::Char('s') => Input::SwitchAnimation,
+        KeyCode::Char('q') | KeyCode::Esc => Input::Quit,
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Input::Quit,
+        _ => return Ok(None),
+    };
+    return Ok(Some(input));
+}
+
+fn draw(out: &mut impl Write, strip: &[Rgb], channels: [f32; 3], status: Status) -> io::Result<()> {
+    // The bar's length is the brightest channel, and its colour the mix
+    let brightness = channels.into_iter().fold(0.0, f32::max);
+    let hue = |channel: f32| {
+        if brightness == 0.0 {
+            return 0;
+        }
+        return (channel / brightness * 255.0).round() as u8;
+    };
+    let colour = Color::Rgb {
+        r: hue(channels[0]),
+        g: hue(channels[1]),
+        b: hue(channels[2]),
+    };
+    let lit = (brightness * BAR_WIDTH as f32).round() as usize;
+    let bar: String = (0..BAR_WIDTH)
+        .map(|i| if i < lit { '█' } else { '░' })
+        .collect();
+    write!(
+        out,
+        "\r{}",
+        terminal::Clear(terminal::ClearType::CurrentLine)
+    )?;
+    // Strip pixels as they'd look, dim ones darker
+    for pixel in strip {
+        let colour = Color::Rgb {
+            r: pixel.r,
+            g: pixel.g,
+            b: pixel.b,
+        };
+        write!(out, "{}●", SetForegroundColor(colour))?;
+    }
+    write!(
+        out,
+        "{} [{}{}{}] {:>5.1}%  {:<8} {:>4.1}s  | {} = {} (knob {}%{})",
+        ResetColor,
+        SetForegroundColor(colour),
+        bar,
+        ResetColor,
+        brightness * 100.0,
+        status.phase,
+        status.remaining_ms as f32 / 1000.0,
+        status.current.setting.as_str(),
+        status.current.value,
+        status.knob_pct,
+        if status.picked_up {
+            ""
+        } else {
+            ", turn past value"
+        },
+    )?;
+    return out.flush();
+}
@@ -1,29 +1,83 @@
+#[cfg(feature = "extra-leds")]
+use breathe_core::breathing::Follower;
+use breathe_core::config::SettingName;
+pub use breathe_core::constants::*;
+#[cfg(feature = "strip")]
+use breathe_core::strip::Animation;
+use hal::ledc;
+
 // Pin numbers, where possible (won't help for button)
 pub const POT_PIN_NUM: u8 = 34;
 pub const LED_PIN_NUM: u8 = 22;
+// Builds with the `rgb` feature use the LED pin for red
+#[cfg(feature = "rgb")]
+pub const LED_GREEN_PIN_NUM: u8 = 21;
+#[cfg(feature = "rgb")]
+pub const LED_BLUE_PIN_NUM: u8 = 19;
+
+
+// LED consts. The APB clock is 80 MHz, so the frequency times 2 to the
+// resolution's bits can't go over that, e.g. 16-bit needs 1 kHz or lower.
+pub const LED_DUTY_RESOLUTION: ledc::timer::config::Duty = ledc::timer::config::Duty::Duty13Bit;
+pub const LED_FREQUENCY_KHZ: u32 = 5;
+// LEDs on GPIO18 and GPIO5 for builds with the `extra-leds` feature, with one
+// entry per pin. These make a wave running on from the main LED.
+#[cfg(feature = "extra-leds")]
+pub const EXTRA_LEDS: [Follower; 2] = [Follower::new(15), Follower::new(30)];
+
+
+// Addressable strip consts, for builds with the `strip` feature. The strip's
+// data line goes on GPIO13.
+#[cfg(feature = "strip")]
+pub const STRIP_LEN: usize = 12;
+#[cfg(feature = "strip")]
+pub const STRIP_ANIMATION: Animation = Animation::Bar;
+
+
+// Vibration motor consts, for builds with the `haptic` feature. The motor is
+// switched by a transistor on this pin, and kept gentle as it's held or worn.
+#[cfg(feature = "haptic")]
+pub const HAPTIC_PIN_NUM: u8 = 27;
+#[cfg(feature = "haptic")]
+pub const HAPTIC_STRENGTH_PCT: u8 = 60;
 
 
-// Potentiometer consts
-pub const POT_READ_COUNT: u16 = 5;
-pub const POT_MIN: u16 = 430;
-pub const POT_MAX: u16 = 3410;
-// Artificially hit the max and min segments later by expanding the deadzone
-pub const POT_DEADZONE: u16 = 200;
-pub const POT_SEGMENTS: u16 = 10;
+// Encoder consts, for builds with the `encoder` feature
+#[cfg(feature = "encoder")]
+pub const ENCODER_STEPS_PER_DETENT: i8 = 4;
 
 
-// Config init values
-pub const SEGMENT_MIN: u8 = 0;
-pub const SEGMENT_MAX: u8 = 10;
+// Settings for hardware this build doesn't drive, left out of the pot's
+// rotation. They're still saved and can be set from the console.
+pub const ABSENT_SETTINGS: &[SettingName] = &[
+    #[cfg(not(any(feature = "rgb", feature = "strip")))]
+    SettingName::InhaleColour,
+    #[cfg(not(any(feature = "rgb", feature = "strip")))]
+    SettingName::HoldColour,
+    #[cfg(not(any(feature = "rgb", feature = "strip")))]
+    SettingName::ExhaleColour,
+    #[cfg(not(any(feature = "rgb", feature = "strip")))]
+    SettingName::AirlessColour,
+    #[cfg(not(feature = "buzzer"))]
+    SettingName::VolumePct,
+    #[cfg(not(feature = "buzzer"))]
+    SettingName::CueStyle,
+    #[cfg(not(feature = "haptic"))]
+    SettingName::HapticStyle,
+    // Encoders turn settings by steps whatever this says
+    #[cfg(feature = "encoder")]
+    SettingName::PotMode,
+];
 
-pub const MIN_INHALE_TIME_MS: u16 = 3000u16;
-pub const MAX_INHALE_TIME_MS: u16 = 10000u16;
 
-pub const MIN_EXHALE_TIME_MS: u16 = 3000u16;
-pub const MAX_EXHALE_TIME_MS: u16 = 10000u16;
+// Config storage, matching the `breathe` entry in partitions.csv
+pub const CONFIG_PARTITION_OFFSET: u32 = 0x3F0000;
+pub const CONFIG_PARTITION_SIZE: u32 = 0x10000;
+// Wait for settings to stop changing before writing them to flash
+pub const CONFIG_SAVE_DELAY_MS: u64 = 5000;
 
-pub const MIN_HOLD_TIME_MS: u16 = 1000u16;
-pub const MAX_HOLD_TIME_MS: u16 = 10000u16;
+// Longest command the serial console accepts
+pub const CONSOLE_LINE_LEN: usize = 64;
 
-pub const MIN_AIRLESS_TIME_MS: u16 = 500u16;
-pub const MAX_AIRLESS_TIME_MS: u16 = 10000u16;
+// Input events waiting for the main loop, less one
+pub const EVENT_QUEUE_LEN: usize = 16;
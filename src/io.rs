@@ -1,3 +1,12 @@
 pub mod button;
+#[cfg(feature = "buzzer")]
+pub mod buzzer;
+#[cfg(feature = "encoder")]
+pub mod encoder;
+pub mod flash;
+#[cfg(not(feature = "encoder"))]
 pub mod potentiometer;
 pub mod led;
+#[cfg(feature = "strip")]
+pub mod strip;
+pub mod time;
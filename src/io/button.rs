@@ -1,135 +1,140 @@
+use super::time::CycleClock;
+use breathe_core::event::{Input, InputEvent, Producer};
+use breathe_core::hal::{ButtonSource, Clock};
 use core::cell::RefCell;
 use critical_section::Mutex;
-use esp_println::println;
+use embedded_hal::digital::v2::InputPin;
 use hal::{gpio, interrupt, peripherals};
 
-#[allow(dead_code)]
-pub enum Buttons<'a> {
-    B0(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 0>>),
-    B1(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 1>>),
-    B2(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 2>>),
-    B3(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 3>>),
-    B4(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 4>>),
-    B5(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 5>>),
-    B6(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 6>>),
-    B7(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 7>>),
-    B8(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 8>>),
-    B9(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 9>>),
-    B10(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 10>>),
-    B11(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 11>>),
-    B12(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 12>>),
-    B13(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 13>>),
-    B14(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 14>>),
-    B15(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 15>>),
-    B16(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 16>>),
-    B17(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 17>>),
-    B18(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 18>>),
-    B19(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 19>>),
-    B20(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 20>>),
-    B21(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 21>>),
-    B22(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 22>>),
-    B23(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 23>>),
-    B24(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 24>>),
-    B25(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 25>>),
-    B26(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 26>>),
-    B27(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 27>>),
-    B32(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 32>>),
-    B33(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 33>>),
-    B34(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 34>>),
-    B35(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 35>>),
-    B36(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 36>>),
-    B37(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 37>>),
-    B38(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 38>>),
-    B39(Button<'a, gpio::GpioPin<gpio::Input<gpio::PullDown>, 39>>),
+// Most buttons that can be registered at once
+pub const MAX_BUTTONS: usize = 4;
+
+const NO_BUTTON: Option<Button> = None;
+static BUTTONS: Mutex<RefCell<[Option<Button>; MAX_BUTTONS]>> =
+    Mutex::new(RefCell::new([NO_BUTTON; MAX_BUTTONS]));
+
+// Any input pin, with either pull resistor. Pins come from `degrade()`.
+pub enum ButtonPin {
+    PullUp(gpio::AnyPin<gpio::Input<gpio::PullUp>>),
+    PullDown(gpio::AnyPin<gpio::Input<gpio::PullDown>>),
 }
 
-pub struct Button<'a, GpioPin> {
-    name: &'a str,
-    pin: Mutex<RefCell<GpioPin>>,
-    isr_callback: fn(),
+impl From<gpio::AnyPin<gpio::Input<gpio::PullUp>>> for ButtonPin {
+    fn from(pin: gpio::AnyPin<gpio::Input<gpio::PullUp>>) -> Self {
+        ButtonPin::PullUp(pin)
+    }
 }
 
-impl<'a, GpioPin> Button<'a, GpioPin>
-where
-    GpioPin: gpio::Pin,
-{
-    pub fn new(name: &'a str, gpio_pin: GpioPin, isr_callback: fn()) -> Self {
-        let btn = Button {
-            name,
-            pin: Mutex::new(RefCell::new(gpio_pin)),
-            isr_callback,
-        };
+impl From<gpio::AnyPin<gpio::Input<gpio::PullDown>>> for ButtonPin {
+    fn from(pin: gpio::AnyPin<gpio::Input<gpio::PullDown>>) -> Self {
+        ButtonPin::PullDown(pin)
+    }
+}
 
-        critical_section::with(|cs| {
-            btn.pin.borrow_ref_mut(cs).listen(gpio::Event::FallingEdge);
-        });
-        interrupt::enable(peripherals::Interrupt::GPIO, interrupt::Priority::Priority2)
-            .unwrap();
+impl ButtonPin {
+    fn is_high(&self) -> bool {
+        use ButtonPin::*;
+        match self {
+            PullUp(pin) => return pin.is_high().unwrap_or(false),
+            PullDown(pin) => return pin.is_high().unwrap_or(false),
+        }
+    }
 
-        return btn;
+    fn listen(&mut self, event: gpio::Event) {
+        use gpio::Pin;
+        use ButtonPin::*;
+        match self {
+            PullUp(pin) => pin.listen(event),
+            PullDown(pin) => pin.listen(event),
+        }
+    }
+
+    // Clears the pin's interrupt, returning whether it was set
+    fn take_interrupt(&mut self) -> bool {
+        use gpio::Pin;
+        use ButtonPin::*;
+        let set = match self {
+            PullUp(pin) => pin.is_interrupt_set(),
+            PullDown(pin) => pin.is_interrupt_set(),
+        };
+        if set {
+            match self {
+                PullUp(pin) => pin.clear_interrupt(),
+                PullDown(pin) => pin.clear_interrupt(),
+            }
+        }
+        return set;
     }
 }
 
-pub trait Interruptor {
-    fn isr(&self);
+// The level a pin reads while its button is pressed
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ActiveLevel {
+    High,
+    Low,
 }
 
-impl Interruptor for Buttons<'_> {
-    fn isr(&self) {
-        use Buttons::*;
-        match self {
-            B0(btn) => btn.isr(),
-            B1(btn) => btn.isr(),
-            B2(btn) => btn.isr(),
-            B3(btn) => btn.isr(),
-            B4(btn) => btn.isr(),
-            B5(btn) => btn.isr(),
-            B6(btn) => btn.isr(),
-            B7(btn) => btn.isr(),
-            B8(btn) => btn.isr(),
-            B9(btn) => btn.isr(),
-            B10(btn) => btn.isr(),
-            B11(btn) => btn.isr(),
-            B12(btn) => btn.isr(),
-            B13(btn) => btn.isr(),
-            B14(btn) => btn.isr(),
-            B15(btn) => btn.isr(),
-            B16(btn) => btn.isr(),
-            B17(btn) => btn.isr(),
-            B18(btn) => btn.isr(),
-            B19(btn) => btn.isr(),
-            B20(btn) => btn.isr(),
-            B21(btn) => btn.isr(),
-            B22(btn) => btn.isr(),
-            B23(btn) => btn.isr(),
-            B24(btn) => btn.isr(),
-            B25(btn) => btn.isr(),
-            B26(btn) => btn.isr(),
-            B27(btn) => btn.isr(),
-            B32(btn) => btn.isr(),
-            B33(btn) => btn.isr(),
-            B34(btn) => btn.isr(),
-            B35(btn) => btn.isr(),
-            B36(btn) => btn.isr(),
-            B37(btn) => btn.isr(),
-            B38(btn) => btn.isr(),
-            B39(btn) => btn.isr(),
-        }
+pub struct Button {
+    pub active: ActiveLevel,
+    pin: ButtonPin,
+    // Timestamps edges from inside the interrupt
+    clock: CycleClock,
+}
+
+impl Button {
+    // A pull-down pin is taken to be pulled high when pressed, and a pull-up
+    // pin low. Change `active` for anything wired the other way round.
+    pub fn new(pin: impl Into<ButtonPin>, clock: CycleClock) -> Self {
+        let pin = pin.into();
+        let active = match pin {
+            ButtonPin::PullUp(_) => ActiveLevel::Low,
+            ButtonPin::PullDown(_) => ActiveLevel::High,
+        };
+        Button { active, pin, clock }
     }
 }
 
-impl<GpioPin> Interruptor for Button<'_, GpioPin>
-where
-    GpioPin: gpio::Pin,
-{
-    fn isr(&self) {
-        critical_section::with(|cs| {
-            let mut pin = self.pin.borrow_ref_mut(cs);
-            if pin.is_interrupt_set() {
-                println!("Button {} pressed!", self.name);
-                (self.isr_callback)();
-                pin.clear_interrupt();
-            }
-        });
+impl ButtonSource for Button {
+    fn is_pressed(&mut self) -> bool {
+        self.pin.is_high() == (self.active == ActiveLevel::High)
     }
 }
+
+// Start reporting the button's edges, returning the index its events carry.
+// Gestures need to see both the press and the release, so it listens for
+// either edge.
+pub fn register(mut button: Button) -> Result<usize, Button> {
+    let index = critical_section::with(|cs| {
+        let mut buttons = BUTTONS.borrow_ref_mut(cs);
+        let index = match buttons.iter().position(|slot| slot.is_none()) {
+            Some(index) => index,
+            None => return Err(button),
+        };
+        button.pin.listen(gpio::Event::AnyEdge);
+        buttons[index] = Some(button);
+        return Ok(index);
+    })?;
+    interrupt::enable(peripherals::Interrupt::GPIO, interrupt::Priority::Priority2).unwrap();
+    return Ok(index);
+}
+
+// For the GPIO interrupt: clear any button interrupts and queue their edges.
+// Edges are dropped if the queue is full.
+pub fn handle_interrupt<const N: usize>(events: &mut Producer<'_, N>) {
+    critical_section::with(|cs| {
+        let mut buttons = BUTTONS.borrow_ref_mut(cs);
+        for (index, slot) in buttons.iter_mut().enumerate() {
+            if let Some(button) = slot {
+                if button.pin.take_interrupt() {
+                    let _ = events.push(InputEvent {
+                        at_ms: button.clock.now_ms(),
+                        input: Input::ButtonEdge {
+                            button: index as u8,
+                            pressed: button.is_pressed(),
+                        },
+                    });
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,39 @@
+use breathe_core::hal::ToneOutput;
+use hal::{gpio, ledc, prelude::*};
+
+// Timer resolution for the buzzer. Pitch comes from the timer's frequency and
+// volume from the duty, so it doesn't need to be fine.
+pub const BUZZER_DUTY_RESOLUTION: ledc::timer::config::Duty = ledc::timer::config::Duty::Duty10Bit;
+const APB_CLOCK_HZ: u64 = 80_000_000;
+
+// A piezo buzzer on its own LEDC channel and timer. Retuning the timer would
+// retune anything else on it, so nothing else can share it.
+pub struct Buzzer<'a, O>
+where
+    O: gpio::OutputPin,
+{
+    pub channel: ledc::channel::Channel<'a, ledc::HighSpeed, O>,
+    pub timer: &'a ledc::timer::Timer<'a, ledc::HighSpeed>,
+}
+
+impl<'a, O> ToneOutput for Buzzer<'a, O>
+where
+    O: gpio::OutputPin,
+    ledc::channel::Channel<'a, ledc::HighSpeed, O>: ledc::channel::ChannelHW<O>,
+{
+    fn play(&mut self, frequency_hz: u16, volume_pct: u8) {
+        use ledc::timer::TimerHW;
+        // The timer's divider, with 8 fractional bits, as `configure` works it out
+        let precision = 1u64 << BUZZER_DUTY_RESOLUTION as u64;
+        let divisor = (APB_CLOCK_HZ << 8) / frequency_hz.max(1) as u64 / precision;
+        self.timer.configure_hw(divisor as u32);
+        self.timer.update_hw();
+        // A piezo is loudest at half duty
+        let duty_pct = (volume_pct.min(100) / 2).max(1);
+        self.channel.set_duty(duty_pct).unwrap();
+    }
+
+    fn stop(&mut self) {
+        self.channel.set_duty(0).unwrap();
+    }
+}
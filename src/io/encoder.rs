@@ -0,0 +1,80 @@
+use super::time::CycleClock;
+use breathe_core::encoder::QuadratureDecoder;
+use breathe_core::event::{Input, InputEvent, Producer};
+use breathe_core::hal::Clock;
+use core::cell::RefCell;
+use critical_section::Mutex;
+use embedded_hal::digital::v2::InputPin;
+use hal::{gpio, interrupt, peripherals};
+
+static ENCODER: Mutex<RefCell<Option<Encoder>>> = Mutex::new(RefCell::new(None));
+
+type EncoderPin = gpio::AnyPin<gpio::Input<gpio::PullUp>>;
+
+// A rotary encoder's two quadrature channels, each switching to ground. Its
+// push switch, if it has one, is registered as a button separately.
+pub struct Encoder {
+    a: EncoderPin,
+    b: EncoderPin,
+    decoder: QuadratureDecoder,
+    // Timestamps turns from inside the interrupt
+    clock: CycleClock,
+}
+
+impl Encoder {
+    pub fn new(a: EncoderPin, b: EncoderPin, steps_per_detent: i8, clock: CycleClock) -> Self {
+        Encoder {
+            a,
+            b,
+            decoder: QuadratureDecoder::new(steps_per_detent),
+            clock,
+        }
+    }
+}
+
+// Start reporting turns. Every edge of either channel is needed to follow
+// the quadrature sequence.
+pub fn register(mut encoder: Encoder) {
+    use gpio::Pin;
+    critical_section::with(|cs| {
+        encoder.a.listen(gpio::Event::AnyEdge);
+        encoder.b.listen(gpio::Event::AnyEdge);
+        ENCODER.borrow_ref_mut(cs).replace(encoder);
+    });
+    interrupt::enable(peripherals::Interrupt::GPIO, interrupt::Priority::Priority2).unwrap();
+}
+
+// For the GPIO interrupt: clear the encoder's interrupts and queue any turn.
+// Turns are dropped if the queue is full.
+pub fn handle_interrupt<const N: usize>(events: &mut Producer<'_, N>) {
+    use gpio::Pin;
+    critical_section::with(|cs| {
+        let mut encoder = ENCODER.borrow_ref_mut(cs);
+        let encoder = match encoder.as_mut() {
+            Some(encoder) => encoder,
+            None => return,
+        };
+
+        let a_set = encoder.a.is_interrupt_set();
+        let b_set = encoder.b.is_interrupt_set();
+        if !a_set && !b_set {
+            return;
+        }
+        if a_set {
+            encoder.a.clear_interrupt();
+        }
+        if b_set {
+            encoder.b.clear_interrupt();
+        }
+
+        let a = encoder.a.is_high().unwrap_or(false);
+        let b = encoder.b.is_high().unwrap_or(false);
+        let detents = encoder.decoder.update(a, b);
+        if detents != 0 {
+            let _ = events.push(InputEvent {
+                at_ms: encoder.clock.now_ms(),
+                input: Input::EncoderTurn { detents },
+            });
+        }
+    });
+}
@@ -0,0 +1,49 @@
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+use esp_storage::FlashStorage;
+
+use breathe_core::storage::{self, StorageError};
+
+// The reserved config partition on the SPI flash chip
+pub struct PartitionFlash {
+    storage: FlashStorage,
+    offset: u32,
+    size: u32,
+}
+
+impl PartitionFlash {
+    pub fn new(offset: u32, size: u32) -> Self {
+        PartitionFlash {
+            storage: FlashStorage::new(),
+            offset,
+            size,
+        }
+    }
+
+    fn absolute(&self, offset: u32, len: usize) -> Result<u32, StorageError> {
+        if offset + len as u32 > self.size {
+            return Err(StorageError::OutOfBounds);
+        }
+        return Ok(self.offset + offset);
+    }
+}
+
+impl storage::Flash for PartitionFlash {
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), StorageError> {
+        let from = self.absolute(offset, buf.len())?;
+        self.storage
+            .read(from, buf)
+            .map_err(|_| StorageError::Flash)
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), StorageError> {
+        let from = self.absolute(offset, data.len())?;
+        NorFlash::write(&mut self.storage, from, data).map_err(|_| StorageError::Flash)
+    }
+
+    fn erase_sector(&mut self, offset: u32) -> Result<(), StorageError> {
+        let from = self.absolute(offset, storage::SECTOR_SIZE as usize)?;
+        self.storage
+            .erase(from, from + storage::SECTOR_SIZE)
+            .map_err(|_| StorageError::Flash)
+    }
+}
@@ -1,7 +1,6 @@
+use breathe_core::hal::PwmFader;
 use esp_backtrace as _;
-use hal::{gpio, ledc, prelude::*};
-
-use crate::constants;
+use hal::{gpio, ledc};
 
 pub struct Led<'a, S, O>
 where
@@ -10,6 +9,8 @@ where
 {
     pub channel: Option<ledc::channel::Channel<'a, S, O>>,
     pub ledc: Option<&'a ledc::LEDC<'a>>,
+    // Full duty at the timer's resolution
+    max_duty: u32,
 }
 
 impl<'a, S, O> Led<'a, S, O>
@@ -17,93 +18,29 @@ where
     S: ledc::timer::TimerSpeed,
     O: gpio::OutputPin,
 {
-    pub fn new(ledc: &'a ledc::LEDC) -> Self {
+    // `resolution` must match the timer the channel is given
+    pub fn new(ledc: &'a ledc::LEDC, resolution: ledc::timer::config::Duty) -> Self {
         Led {
             channel: None,
             ledc: Some(ledc),
+            max_duty: (1u32 << resolution as u32) - 1,
         }
     }
 }
 
-pub struct BreathingLed<'a, S, O> where
-    S: ledc::timer::TimerSpeed,
-    O: gpio::OutputPin,
-{
-    pub led: Led<'a, S, O>,
-    pub min_duty: u8,
-    pub max_duty: u8,
-    pub breathe_in_time_ms: u16,
-    pub breathe_out_time_ms: u16,
-}
-
-impl<'a, S, O> BreathingLed<'a, S, O>
-where
-    S: ledc::timer::TimerSpeed,
-    O: gpio::OutputPin,
-{
-    pub fn new(ledc: &'a ledc::LEDC) -> Self {
-        BreathingLed {
-            led: Led::new(ledc),
-            min_duty: 0,
-            max_duty: 100,
-            breathe_in_time_ms: constants::MIN_INHALE_TIME_MS,
-            breathe_out_time_ms: constants::MIN_EXHALE_TIME_MS,
-        }
-    }
-}
-
-// TODO: One, non-blocking, breathing routine
-pub trait Breather {
-    fn breathe_in(&self);
-    fn breathe_out(&self);
-}
-
-impl<'a, S, O> Breather for Led<'a, S, O>
-where
-    S: ledc::timer::TimerSpeed,
-    O: gpio::OutputPin,
-    ledc::channel::Channel<'a, S, O>: ledc::channel::ChannelHW<O>,
-{
-    fn breathe_in(&self) {
-        self.channel
-            .as_ref()
-            .unwrap()
-            .start_duty_fade(0, 100, 1000)
-            .unwrap();
-        while self.channel.as_ref().unwrap().is_duty_fade_running() {}
-    }
-
-    fn breathe_out(&self) {
-        self.channel
-            .as_ref()
-            .unwrap()
-            .start_duty_fade(100, 0, 1000)
-            .unwrap();
-        while self.channel.as_ref().unwrap().is_duty_fade_running() {}
-    }
-}
-
-impl<'a, S, O> Breather for BreathingLed<'a, S, O>
+impl<'a, S, O> PwmFader for Led<'a, S, O>
 where
     S: ledc::timer::TimerSpeed,
     O: gpio::OutputPin,
     ledc::channel::Channel<'a, S, O>: ledc::channel::ChannelHW<O>,
 {
-    fn breathe_in(&self) {
-        self.led.channel
-            .as_ref()
-            .unwrap()
-            .start_duty_fade(self.min_duty, self.max_duty, self.breathe_in_time_ms)
-            .unwrap();
-        while self.led.channel.as_ref().unwrap().is_duty_fade_running() {}
+    fn max_duty(&self) -> u32 {
+        self.max_duty
     }
 
-    fn breathe_out(&self) {
-        self.led.channel
-            .as_ref()
-            .unwrap()
-            .start_duty_fade(self.max_duty, self.min_duty, self.breathe_out_time_ms)
-            .unwrap();
-        while self.led.channel.as_ref().unwrap().is_duty_fade_running() {}
+    fn set_duty(&mut self, duty: u32) {
+        use ledc::channel::ChannelHW;
+        let channel = self.channel.as_ref().unwrap();
+        channel.set_duty_hw(duty.min(self.max_duty));
     }
 }
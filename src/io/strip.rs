@@ -0,0 +1,48 @@
+use breathe_core::{
+    breathing::BreathingEngine, colour::Rgb, config::Config, indicator::BreathIndicator,
+    strip::StripRenderer,
+};
+use core::fmt::Debug;
+use esp_println::println;
+use smart_leds::{SmartLedsWrite, RGB8};
+
+// Send a frame from a `StripRenderer` to the strip, which keeps showing it
+// until the next one
+pub fn write_frame<S>(strip: &mut S, frame: &[Rgb]) -> Result<(), S::Error>
+where
+    S: SmartLedsWrite,
+    S::Color: From<RGB8>,
+{
+    strip.write(
+        frame
+            .iter()
+            .map(|pixel| RGB8::new(pixel.r, pixel.g, pixel.b)),
+    )
+}
+
+// A strip of N pixels following the breath. Only frames that changed are
+// sent, as each write holds up the loop.
+pub struct Strip<S, const N: usize> {
+    pub led: S,
+    pub renderer: StripRenderer<N>,
+}
+
+impl<S, const N: usize> BreathIndicator for Strip<S, N>
+where
+    S: SmartLedsWrite,
+    S::Color: From<RGB8>,
+    S::Error: Debug,
+{
+    fn configure(&mut self, config: &Config) {
+        self.renderer.configure(config);
+    }
+
+    fn render(&mut self, engine: &BreathingEngine, now_ms: u64) {
+        self.renderer.render(engine, now_ms);
+        if self.renderer.take_changed() {
+            if let Err(e) = write_frame(&mut self.led, self.renderer.frame()) {
+                println!("Strip write failed: {:?}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use breathe_core::hal::Clock;
+use core::cell::Cell;
+use critical_section::Mutex;
+use hal::{clock, xtensa_lx};
+
+// Last raw cycle count seen, and the total cycles elapsed since boot
+static CYCLES: Mutex<Cell<(u32, u64)>> = Mutex::new(Cell::new((0, 0)));
+
+// Millisecond clock built on the CPU cycle counter. The raw counter wraps every
+// few seconds, so `now_ms` must be called more often than that to keep time.
+#[derive(Copy, Clone)]
+pub struct CycleClock {
+    cycles_per_ms: u64,
+}
+
+impl CycleClock {
+    pub fn new(clocks: &clock::Clocks) -> Self {
+        CycleClock {
+            cycles_per_ms: clocks.cpu_clock.to_Hz() as u64 / 1000,
+        }
+    }
+}
+
+impl Clock for CycleClock {
+    fn now_ms(&self) -> u64 {
+        critical_section::with(|cs| {
+            let cycles = CYCLES.borrow(cs);
+            let (last, total) = cycles.get();
+            let raw = xtensa_lx::timer::get_cycle_count();
+            let total = total + raw.wrapping_sub(last) as u64;
+            cycles.set((raw, total));
+            total / self.cycles_per_ms
+        })
+    }
+}
@@ -1,151 +1,424 @@
 #![no_std]
 #![no_main]
 
-mod config;
 mod constants;
 mod io;
 
-use esp_backtrace as _;
-use esp_println::println;
-use hal::{adc, analog, clock, gpio, ledc, peripherals, prelude::*};
-use io::{
-    button,
-    led::{self, Breather},
-    potentiometer,
+use breathe_core::{
+    breathing, config,
+    event::{EventQueue, Input, Producer},
+    gesture::{self, ButtonEvent},
+    hal::Clock,
+    indicator::{BreathIndicator, LogIndicator},
+    shell, storage,
 };
-
 use core::cell::RefCell;
 use critical_section::Mutex;
+use esp_backtrace as _;
+use esp_println::println;
+use hal::{clock, gpio, ledc, peripherals, prelude::*};
+use io::{button, flash, led, time};
+
+// Optional outputs: an RGB LED, extra LEDs, an addressable strip, a buzzer
+// and a vibration motor
+#[cfg(feature = "haptic")]
+use breathe_core::haptic;
+#[cfg(any(not(feature = "rgb"), feature = "extra-leds"))]
+use breathe_core::led::BreathingLed;
+#[cfg(feature = "rgb")]
+use breathe_core::led::RgbLed;
+#[cfg(feature = "buzzer")]
+use {breathe_core::cue, io::buzzer};
+#[cfg(feature = "strip")]
+use {breathe_core::strip, hal::rmt, io::strip as strip_io};
+
+// Settings come from either a potentiometer or a rotary encoder
+#[cfg(feature = "encoder")]
+use io::encoder;
+#[cfg(not(feature = "encoder"))]
+use {
+    breathe_core::{
+        calibration,
+        hal::{ButtonSource, PwmFader},
+        pot,
+    },
+    hal::{adc, analog},
+    io::potentiometer,
+};
 
 type LedPinType = gpio::GpioPin<gpio::Output<gpio::PushPull>, { constants::LED_PIN_NUM }>;
+#[cfg(feature = "rgb")]
+type GreenPinType = gpio::GpioPin<gpio::Output<gpio::PushPull>, { constants::LED_GREEN_PIN_NUM }>;
+#[cfg(feature = "rgb")]
+type BluePinType = gpio::GpioPin<gpio::Output<gpio::PushPull>, { constants::LED_BLUE_PIN_NUM }>;
+#[cfg(feature = "haptic")]
+type HapticPinType = gpio::GpioPin<gpio::Output<gpio::PushPull>, { constants::HAPTIC_PIN_NUM }>;
+#[cfg(feature = "extra-leds")]
+type ExtraLedPinType = gpio::AnyPin<gpio::Output<gpio::PushPull>>;
+#[cfg(not(feature = "encoder"))]
 type PotPinType = gpio::GpioPin<gpio::Analog, { constants::POT_PIN_NUM }>;
+#[cfg(not(feature = "encoder"))]
+type PotType = pot::Potentiometer<potentiometer::PotAdc<'static, PotPinType, adc::ADC1>>;
 
-static mut BUTTONS: [Option<button::Buttons>; 10] =
-    [None, None, None, None, None, None, None, None, None, None];
-
-static CONFIG: Mutex<RefCell<Option<config::Config>>> = Mutex::new(RefCell::new(None));
+// Filled by interrupts and drained by the main loop, which is the only place
+// anything acts on input
+static EVENTS: EventQueue<{ constants::EVENT_QUEUE_LEN }> = EventQueue::new();
+// The interrupts' end of `EVENTS`, handed over before any are enabled
+static EVENT_PRODUCER: Mutex<RefCell<Option<Producer<'static, { constants::EVENT_QUEUE_LEN }>>>> =
+    Mutex::new(RefCell::new(None));
 
 #[entry]
 fn main() -> ! {
+    let (producer, mut events) = EVENTS.split().unwrap();
+    critical_section::with(|cs| EVENT_PRODUCER.borrow_ref_mut(cs).replace(producer));
+
     let peripherals = peripherals::Peripherals::take();
     let system = peripherals.SYSTEM.split();
+    #[cfg(not(feature = "encoder"))]
     let analog = peripherals.SENS.split();
     let clocks = clock::ClockControl::boot_defaults(system.clock_control).freeze();
     let io = gpio::IO::new(peripherals.GPIO, peripherals.IO_MUX);
 
-    // Init config
-    critical_section::with(|cs| {
-        CONFIG.borrow_ref_mut(cs).replace(config::Config::new());
-    });
+    // Init config from flash, falling back to defaults if there's nothing valid
+    let mut store = storage::ConfigStore::new(
+        flash::PartitionFlash::new(
+            constants::CONFIG_PARTITION_OFFSET,
+            constants::CONFIG_PARTITION_SIZE,
+        ),
+        constants::CONFIG_PARTITION_SIZE,
+    );
+    let stored_config = store.load();
+    if stored_config.is_none() {
+        println!("No stored config, using defaults");
+    }
+    let mut conf = stored_config.unwrap_or_else(config::Config::new);
+    for setting in constants::ABSENT_SETTINGS {
+        conf.hide(*setting);
+    }
+    let mut engine = breathing::BreathingEngine::new(breathing::Timings::from_config(&conf));
+
+    let clock = time::CycleClock::new(&clocks);
 
     // Set up potentiometer
-    let mut pot: potentiometer::Potentiometer<PotPinType, adc::ADC1> =
-        potentiometer::Potentiometer::new();
-    set_up_potentiometer(analog, io.pins.gpio34.into_analog(), &mut pot);
-
-    // Set up button
-    let btn: button::Buttons<'static> = button::Buttons::B15(button::Button::new(
-        "Mode Selector",
-        io.pins.gpio15.into_pull_down_input(),
-        next_setting_callback,
-    ));
-    unsafe {
-        BUTTONS[0] = Some(btn);
+    #[cfg(not(feature = "encoder"))]
+    let mut pot = set_up_potentiometer(analog, io.pins.gpio34.into_analog());
+    #[cfg(not(feature = "encoder"))]
+    if let Some(calibration) = store.calibration() {
+        pot.apply_calibration(calibration);
     }
 
+    // Or the encoder, whose channels switch to ground
+    #[cfg(feature = "encoder")]
+    encoder::register(encoder::Encoder::new(
+        io.pins.gpio32.into_pull_up_input().degrade(),
+        io.pins.gpio33.into_pull_up_input().degrade(),
+        constants::ENCODER_STEPS_PER_DETENT,
+        clock,
+    ));
+
+    // Set up button. It's read directly until registered, for calibration.
+    #[cfg(not(feature = "encoder"))]
+    let mut btn = button::Button::new(
+        io.pins.gpio15.into_pull_down_input().degrade(),
+        clock,
+    );
+    // With an encoder, its push switch is the Mode Selector
+    #[cfg(feature = "encoder")]
+    let btn = button::Button::new(
+        io.pins.gpio25.into_pull_up_input().degrade(),
+        clock,
+    );
+
     // LED setup
     let ledc = ledc::LEDC::new(peripherals.LEDC, &clocks);
     let mut hstimer = ledc.get_timer::<ledc::HighSpeed>(ledc::timer::Number::Timer0);
-    let led_pin = io.pins.gpio22.into_push_pull_output();
-    let mut breathing_led: led::BreathingLed<
-        ledc::HighSpeed,
-        gpio::GpioPin<gpio::Output<gpio::PushPull>, { constants::LED_PIN_NUM }>,
-    > = led::BreathingLed::new(&ledc);
-    set_up_led(led_pin, &mut hstimer, &mut breathing_led);
+    set_up_led_timer(&mut hstimer);
+    let mut led: led::Led<ledc::HighSpeed, LedPinType> =
+        led::Led::new(&ledc, constants::LED_DUTY_RESOLUTION);
+    set_up_led(
+        io.pins.gpio22.into_push_pull_output(),
+        ledc::channel::Number::Channel0,
+        &hstimer,
+        &mut led,
+    );
+    #[cfg(not(feature = "rgb"))]
+    let mut breathing_led = BreathingLed::new(led);
+
+    // With an RGB LED, that's the red channel
+    #[cfg(feature = "rgb")]
+    let mut breathing_led = {
+        let mut green: led::Led<ledc::HighSpeed, GreenPinType> =
+            led::Led::new(&ledc, constants::LED_DUTY_RESOLUTION);
+        set_up_led(
+            io.pins.gpio21.into_push_pull_output(),
+            ledc::channel::Number::Channel1,
+            &hstimer,
+            &mut green,
+        );
+        let mut blue: led::Led<ledc::HighSpeed, BluePinType> =
+            led::Led::new(&ledc, constants::LED_DUTY_RESOLUTION);
+        set_up_led(
+            io.pins.gpio19.into_push_pull_output(),
+            ledc::channel::Number::Channel2,
+            &hstimer,
+            &mut blue,
+        );
+        RgbLed::new(led, green, blue)
+    };
+
+    // Extra LEDs, each following the breath as set in `EXTRA_LEDS`. They use
+    // the channels after the RGB LED's, so either can be added.
+    #[cfg(feature = "extra-leds")]
+    let mut extra_leds = {
+        let pins: [ExtraLedPinType; constants::EXTRA_LEDS.len()] = [
+            io.pins.gpio18.into_push_pull_output().degrade(),
+            io.pins.gpio5.into_push_pull_output().degrade(),
+        ];
+        let mut numbers = [
+            ledc::channel::Number::Channel3,
+            ledc::channel::Number::Channel4,
+        ]
+        .into_iter();
+        let mut followers = constants::EXTRA_LEDS.into_iter();
+        pins.map(|pin| {
+            let mut extra: led::Led<ledc::HighSpeed, ExtraLedPinType> =
+                led::Led::new(&ledc, constants::LED_DUTY_RESOLUTION);
+            set_up_led(pin, numbers.next().unwrap(), &hstimer, &mut extra);
+            let mut extra = BreathingLed::new(extra);
+            extra.follower = followers.next();
+            extra
+        })
+    };
+
+    // The strip is driven by the RMT peripheral, with 24 pulses per pixel and
+    // one to end on
+    #[cfg(feature = "strip")]
+    let mut strip_led = {
+        let rmt = rmt::Rmt::new(peripherals.RMT, 80u32.MHz(), &clocks).unwrap();
+        let buffer = [0u32; constants::STRIP_LEN * 24 + 1];
+        strip_io::Strip {
+            led: esp_hal_smartled::SmartLedsAdapter::new(
+                rmt.channel0,
+                io.pins.gpio13,
+                buffer,
+                &clocks,
+            ),
+            renderer: strip::StripRenderer::<{ constants::STRIP_LEN }>::new(
+                constants::STRIP_ANIMATION,
+            ),
+        }
+    };
+
+    // The buzzer gets a timer to itself, as its pitch is the timer's frequency
+    #[cfg(feature = "buzzer")]
+    let mut buzzer_timer = ledc.get_timer::<ledc::HighSpeed>(ledc::timer::Number::Timer1);
+    #[cfg(feature = "buzzer")]
+    let mut cues = {
+        buzzer_timer
+            .configure(ledc::timer::config::Config {
+                duty: buzzer::BUZZER_DUTY_RESOLUTION,
+                clock_source: ledc::timer::HSClockSource::APBClk,
+                frequency: 440u32.Hz(),
+            })
+            .unwrap();
+        let mut channel = ledc.get_channel(
+            ledc::channel::Number::Channel5,
+            io.pins.gpio26.into_push_pull_output(),
+        );
+        channel
+            .configure(ledc::channel::config::Config {
+                timer: &buzzer_timer,
+                duty_pct: 0,
+                pin_config: ledc::channel::config::PinConfig::PushPull,
+            })
+            .unwrap();
+        cue::CuePlayer::new(buzzer::Buzzer {
+            channel,
+            timer: &buzzer_timer,
+        })
+    };
+
+    // The vibration motor's transistor is switched like an LED, on the LEDs'
+    // timer
+    #[cfg(feature = "haptic")]
+    let mut haptic_motor = {
+        let mut motor: led::Led<ledc::HighSpeed, HapticPinType> =
+            led::Led::new(&ledc, constants::LED_DUTY_RESOLUTION);
+        set_up_led(
+            io.pins.gpio27.into_push_pull_output(),
+            ledc::channel::Number::Channel6,
+            &hstimer,
+            &mut motor,
+        );
+        let mut haptic_motor = haptic::HapticMotor::new(motor);
+        haptic_motor.strength_pct = constants::HAPTIC_STRENGTH_PCT;
+        haptic_motor
+    };
+
+    // Each phase is logged as it starts
+    let mut log = LogIndicator {
+        out: esp_println::Printer,
+    };
+
+    // Holding the Mode Selector button through boot enters pot calibration
+    #[cfg(not(feature = "encoder"))]
+    if calibration_requested(&mut btn, &clock) {
+        #[cfg(not(feature = "rgb"))]
+        let blinker = &mut breathing_led.fader;
+        #[cfg(feature = "rgb")]
+        let blinker = &mut breathing_led.green;
+        let calibration = calibrate(&mut pot, &mut btn, blinker, &clock);
+        match calibration {
+            Some(calibration) => {
+                println!(
+                    "Pot calibrated to {} - {}",
+                    calibration.min, calibration.max
+                );
+                pot.apply_calibration(calibration);
+                store.set_calibration(calibration);
+                if let Err(e) = store.save(&conf) {
+                    println!("Calibration save failed: {:?}", e);
+                }
+            }
+            None => println!("Calibration failed, keeping the previous one"),
+        }
+    }
+
+    let mode_selector = button::register(btn).ok();
+    if mode_selector.is_none() {
+        println!("No room to register the Mode Selector button");
+    }
+    let mut mode_selector_gestures =
+        gesture::GestureRecognizer::new(gesture::GestureTimings::new());
+
+    // Serial console on UART0, sharing the port with esp_println's output
+    let mut serial = hal::Uart::new(peripherals.UART0, &clocks);
+    let mut console = shell::LineBuffer::<{ constants::CONSOLE_LINE_LEN }>::new();
+
+    // Only turning the pot changes a setting, so the stored one survives boot
+    #[cfg(not(feature = "encoder"))]
+    let mut last_position = pot.read_position();
 
     loop {
-        let pot_value: &mut u16 = &mut 0;
-        pot.read(pot_value);
-        println!("Pot ADC reading = {}", pot_value);
-        critical_section::with(|cs| {
-            let mut conf = CONFIG.borrow_ref_mut(cs);
-            conf.as_mut()
-                .unwrap()
-                .adjust_current_setting(*pot_value as u8);
-            let current = conf.as_mut().unwrap().current_item();
-            println!(
-                "Current setting: {} = {}",
-                current.setting.as_str(),
-                current.value
-            );
+        while let Some(event) = events.pop() {
+            match event.input {
+                Input::ButtonEdge { button, pressed } if Some(button as usize) == mode_selector => {
+                    if let Some(gesture) = mode_selector_gestures.update(event.at_ms, pressed) {
+                        on_mode_selector(gesture, &mut conf, &mut engine, &mut store, &clock);
+                    }
+                }
+                Input::EncoderTurn { detents } => {
+                    conf.turn_current_setting(detents as i16);
+                    let current = conf.current_item();
+                    println!(
+                        "Current setting: {} = {}",
+                        current.setting.as_str(),
+                        current.value
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        // Gestures like a hold finish without an edge
+        if let Some(gesture) = mode_selector_gestures.poll(clock.now_ms()) {
+            on_mode_selector(gesture, &mut conf, &mut engine, &mut store, &clock);
+        }
+
+        let now = clock.now_ms();
+
+        while let Ok(byte) = serial.read() {
+            if let Some(line) = console.push(byte) {
+                match shell::parse(line) {
+                    Ok(command) => run_command(command, &mut conf, &mut store),
+                    Err(e) => println!("{}", e.as_str()),
+                }
+            }
+        }
+
+        #[cfg(not(feature = "encoder"))]
+        {
+            let position = pot.read_position();
+            if last_position != position {
+                conf.adjust_current_setting(position);
+                let current = conf.current_item();
+                println!(
+                    "Current setting: {} = {}",
+                    current.setting.as_str(),
+                    current.value
+                );
+                if !conf.is_picked_up() {
+                    println!("Turn the pot past the current value to change it");
+                }
+            }
+            last_position = position;
+        }
 
-            breathing_led.breathe_in_time_ms = conf
-                .as_mut()
-                .unwrap()
-                .get(config::SettingName::InhaleTimeMs)
-                .unwrap_or_else(|| return constants::MIN_INHALE_TIME_MS);
-            breathing_led.breathe_out_time_ms = conf
-                .as_mut()
-                .unwrap()
-                .get(config::SettingName::ExhaleTimeMs)
-                .unwrap_or_else(|| return constants::MIN_EXHALE_TIME_MS);
-            breathing_led.max_duty = conf
-                .as_mut()
-                .unwrap()
-                .get(config::SettingName::BrightnessPct)
-                .unwrap_or_else(|| return 100) as u8;
-        });
-
-        println!("Breathe in");
-        breathing_led.breathe_in();
-
-        println!("Breathe out");
-        breathing_led.breathe_out();
+        match store.save_when_settled(&conf, now, constants::CONFIG_SAVE_DELAY_MS) {
+            Some(Ok(())) => println!("Config saved"),
+            Some(Err(e)) => println!("Config save failed: {:?}", e),
+            None => {}
+        }
+
+        // Every output follows the breath through the same list, picking up
+        // settings each time round so they take effect mid-phase
+        let indicators: &mut [&mut dyn BreathIndicator] = &mut [
+            &mut log,
+            &mut breathing_led,
+            #[cfg(feature = "extra-leds")]
+            &mut extra_leds,
+            #[cfg(feature = "strip")]
+            &mut strip_led,
+            #[cfg(feature = "buzzer")]
+            &mut cues,
+            #[cfg(feature = "haptic")]
+            &mut haptic_motor,
+        ];
+        for indicator in indicators.iter_mut() {
+            indicator.configure(&conf);
+        }
+        engine.set_timings(breathing::Timings::from_config(&conf));
+        engine.drive(now, indicators);
     }
 }
 
-fn set_up_potentiometer(
-    analog: analog::AvailableAnalog,
-    gpio_pin: PotPinType,
-    pot: &mut potentiometer::Potentiometer<PotPinType, adc::ADC1>,
-) {
+#[cfg(not(feature = "encoder"))]
+fn set_up_potentiometer(analog: analog::AvailableAnalog, gpio_pin: PotPinType) -> PotType {
     // ADC instances for pot
     let mut adc1_config = adc::AdcConfig::new();
-    critical_section::with(|cs| {
-        pot.adc_pin
-            .borrow_ref_mut(cs)
-            .replace(adc1_config.enable_pin(gpio_pin, adc::Attenuation::Attenuation6dB))
-    });
-    critical_section::with(|cs| {
-        pot.adc
-            .borrow_ref_mut(cs)
-            .replace(adc::ADC::<adc::ADC1>::adc(analog.adc1, adc1_config).unwrap())
-    });
+    let adc_pin = adc1_config.enable_pin(gpio_pin, adc::Attenuation::Attenuation6dB);
+    let adc = adc::ADC::<adc::ADC1>::adc(analog.adc1, adc1_config).unwrap();
 
+    let mut pot = pot::Potentiometer::new(potentiometer::PotAdc { adc, adc_pin });
     pot.min_val = constants::POT_MIN;
     pot.max_val = constants::POT_MAX;
     pot.deadzone = constants::POT_DEADZONE;
-    pot.segments = constants::POT_SEGMENTS;
     pot.read_count = constants::POT_READ_COUNT;
+    pot.filter = constants::POT_FILTER;
+    return pot;
 }
 
-fn set_up_led<'a>(
-    pin: LedPinType,
-    hstimer: &'a mut ledc::timer::Timer<ledc::HighSpeed>,
-    breathing_led: &mut led::BreathingLed<'a, ledc::HighSpeed, LedPinType>,
-) {
-    let mut ch = breathing_led
-        .led
-        .ledc
-        .unwrap()
-        .get_channel(ledc::channel::Number::Channel0, pin);
+fn set_up_led_timer(hstimer: &mut ledc::timer::Timer<ledc::HighSpeed>) {
     hstimer
         .configure(ledc::timer::config::Config {
-            duty: ledc::timer::config::Duty::Duty10Bit,
+            duty: constants::LED_DUTY_RESOLUTION,
             clock_source: ledc::timer::HSClockSource::APBClk,
-            frequency: 24u32.kHz(),
+            frequency: constants::LED_FREQUENCY_KHZ.kHz(),
         })
         .unwrap();
+}
+
+// Channels share the timer, so each must have its own channel number
+fn set_up_led<'a, O>(
+    pin: O,
+    number: ledc::channel::Number,
+    hstimer: &'a ledc::timer::Timer<ledc::HighSpeed>,
+    led: &mut led::Led<'a, ledc::HighSpeed, O>,
+) where
+    O: gpio::OutputPin,
+    ledc::channel::Channel<'a, ledc::HighSpeed, O>: ledc::channel::ChannelHW<O>,
+{
+    let mut ch = led.ledc.unwrap().get_channel(number, pin);
 
     ch.configure(ledc::channel::config::Config {
         timer: hstimer,
@@ -154,31 +427,128 @@ fn set_up_led<'a>(
     })
     .unwrap();
 
-    breathing_led.led.channel = Some(ch);
+    led.channel = Some(ch);
 }
 
-// For a button ISR callback, to increment the current setting
-fn next_setting_callback() {
-    critical_section::with(|cs| {
-        let mut conf = CONFIG.borrow_ref_mut(cs);
-        conf.as_mut().unwrap().next_item();
-        println!(
-            "Setting changed to {}",
-            conf.as_mut().unwrap().current_item().setting.as_str()
-        );
-    });
+// The button has to be held through boot, so a brief knock doesn't start
+// calibrating
+#[cfg(not(feature = "encoder"))]
+fn calibration_requested(button: &mut impl ButtonSource, clock: &impl Clock) -> bool {
+    let mut gestures = gesture::GestureRecognizer::new(gesture::GestureTimings::new());
+    loop {
+        let pressed = button.is_pressed();
+        if !pressed {
+            return false;
+        }
+        if gestures.update(clock.now_ms(), pressed) == Some(ButtonEvent::Hold) {
+            return true;
+        }
+    }
+}
+
+#[cfg(not(feature = "encoder"))]
+fn calibrate(
+    pot: &mut PotType,
+    button: &mut impl ButtonSource,
+    led: &mut impl PwmFader,
+    clock: &impl Clock,
+) -> Option<calibration::PotCalibration> {
+    println!("Calibrating pot: turn it fully down and press the button");
+    let mut calibrator = calibration::Calibrator::new(clock.now_ms());
+    let mut step = calibrator.step();
+    let mut led_on = false;
+    loop {
+        let now = clock.now_ms();
+        let raw = pot.read_filtered();
+        let pressed = button.is_pressed();
+
+        let next_step = calibrator.poll(now, raw, pressed);
+        if next_step != step {
+            step = next_step;
+            match step {
+                calibration::CalibrationStep::TurnToMax => {
+                    println!("Now turn it fully up and press the button")
+                }
+                calibration::CalibrationStep::Done(calibration) => {
+                    led.set_duty_pct(0);
+                    return Some(calibration);
+                }
+                _ => {
+                    led.set_duty_pct(0);
+                    return None;
+                }
+            }
+        }
+
+        if calibrator.led_on(now) != led_on {
+            led_on = !led_on;
+            led.set_duty_pct(if led_on { 100 } else { 0 });
+        }
+    }
 }
 
-// Turn off interrupt bits set on button press
-// TODO: Extend to handle different button actions
+fn run_command(
+    command: shell::Command,
+    conf: &mut config::Config,
+    store: &mut storage::ConfigStore<flash::PartitionFlash>,
+) {
+    match shell::execute(command, conf, &mut esp_println::Printer) {
+        Ok(shell::Action::Save) => match store.save(conf) {
+            Ok(()) => println!("Config saved"),
+            Err(e) => println!("Config save failed: {:?}", e),
+        },
+        _ => {}
+    }
+}
+
+// Click for the next setting, double click to pause, long press to save and
+// hold to mute the buzzer
+fn on_mode_selector(
+    event: ButtonEvent,
+    conf: &mut config::Config,
+    engine: &mut breathing::BreathingEngine,
+    store: &mut storage::ConfigStore<flash::PartitionFlash>,
+    clock: &impl Clock,
+) {
+    println!("Mode Selector: {}", event.as_str());
+    let now = clock.now_ms();
+    match event {
+        ButtonEvent::Click => {
+            conf.next_item();
+            println!(
+                "Setting changed to {}",
+                conf.current_item().setting.as_str()
+            );
+        }
+        ButtonEvent::DoubleClick if engine.is_paused() => {
+            engine.resume(now);
+            println!("Resumed");
+        }
+        ButtonEvent::DoubleClick => {
+            engine.pause(now);
+            println!("Paused");
+        }
+        ButtonEvent::LongPress => match store.save(conf) {
+            Ok(()) => println!("Config saved"),
+            Err(e) => println!("Config save failed: {:?}", e),
+        },
+        ButtonEvent::Hold => {
+            conf.muted = !conf.muted;
+            println!("{}", if conf.muted { "Muted" } else { "Unmuted" });
+        }
+        ButtonEvent::HoldRepeat => {}
+    }
+}
+
+// Turn off interrupt bits set on button press, and queue the edges
 #[hal::macros::ram]
 #[interrupt]
 unsafe fn GPIO() {
-    use button::Interruptor;
-    for btn_opt in &BUTTONS {
-        match btn_opt {
-            Some(btn) => btn.isr(),
-            _ => {}
+    critical_section::with(|cs| {
+        if let Some(events) = EVENT_PRODUCER.borrow_ref_mut(cs).as_mut() {
+            button::handle_interrupt(events);
+            #[cfg(feature = "encoder")]
+            encoder::handle_interrupt(events);
         }
-    }
+    });
 }